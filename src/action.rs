@@ -3,18 +3,19 @@
 use std::convert::From;
 
 
-#[derive(Eq, PartialEq, Clone)]
+#[derive(Eq, PartialEq, Clone, Debug)]
 pub enum Action {
     PutBlock { pos: usize, rot: usize },
     UseSkill,
 }
 
-#[derive(Eq, PartialEq, Clone, Default)]
+#[derive(Eq, PartialEq, Clone, Default, Debug)]
 pub struct ActionResult {
     pub chains: u8,
     pub obstacle: i32,
     pub skill_guage: i32,
     pub fire_height: i8,
+    pub score: i32,
 }
 
 impl Action {
@@ -60,12 +61,57 @@ impl From<&Action> for u8 {
     }
 }
 
-impl From<&Action> for u128 {
-    fn from(item: &Action) -> Self {
-        match item {
-            Action::PutBlock { pos, rot } => (pos*4+rot+1) as u128,
-            Action::UseSkill => 9*4+1,
+// 手順。1手をu8の値 (1..=37) で6bitずつ詰めて持つ
+pub const MAX_ACTION_SEQ_LEN: usize = 40;
+const ACTIONS_PER_WORD: usize = 64 / 6;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Debug)]
+pub struct ActionSeq {
+    words: [u64; MAX_ACTION_SEQ_LEN.div_ceil(ACTIONS_PER_WORD)],
+    len: u8,
+}
+
+impl ActionSeq {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push(&mut self, action: &Action) {
+        assert!(self.len() < MAX_ACTION_SEQ_LEN, "too many actions");
+        let (word, shift) = (self.len() / ACTIONS_PER_WORD, self.len() % ACTIONS_PER_WORD * 6);
+        self.words[word] |= (u8::from(action) as u64) << shift;
+        self.len += 1;
+    }
+
+    // 1手足したもの
+    pub fn pushed(&self, action: &Action) -> Self {
+        let mut seq = *self;
+        seq.push(action);
+        seq
+    }
+
+    pub fn get(&self, i: usize) -> Option<Action> {
+        if i >= self.len() {
+            return None;
         }
+        let (word, shift) = (i / ACTIONS_PER_WORD, i % ACTIONS_PER_WORD * 6);
+        Some(((self.words[word] >> shift & 0x3F) as u8).into())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Action> + '_ {
+        (0..self.len()).map(move |i| self.get(i).unwrap())
+    }
+
+    pub fn to_vec(&self) -> Vec<Action> {
+        self.iter().collect()
     }
 }
 
@@ -80,7 +126,23 @@ impl std::fmt::Display for Action {
 
 
 impl ActionResult {
-    pub fn new(chains: u8, obstacle: i32, skill_guage: i32, fire_height: i8) -> Self {
-        Self { chains, obstacle, skill_guage, fire_height, }
+    pub fn new(chains: u8, obstacle: i32, skill_guage: i32, fire_height: i8, score: i32) -> Self {
+        Self { chains, obstacle, skill_guage, fire_height, score, }
     }
 }
+
+#[test]
+fn action_test_seq() {
+    let all = Action::all_actions();
+    let actions = (0..MAX_ACTION_SEQ_LEN).map(|i| all[i * 7 % all.len()].clone()).collect::<Vec<_>>();
+    let mut seq = ActionSeq::new();
+    assert!(seq.is_empty());
+    actions.iter().for_each(|a| seq.push(a));
+    assert_eq!(seq.len(), MAX_ACTION_SEQ_LEN);
+    assert_eq!(seq.to_vec(), actions);
+    assert_eq!(seq.get(MAX_ACTION_SEQ_LEN), None);
+
+    let short = ActionSeq::new().pushed(&Action::UseSkill);
+    assert_eq!(short.to_vec(), vec![Action::UseSkill]);
+    assert_ne!(short, ActionSeq::new().pushed(&Action::PutBlock { pos: 0, rot: 0 }));
+}
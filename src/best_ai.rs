@@ -1,147 +1,285 @@
 
 
-use std::str::FromStr;
-use std::io::Read;
+use std::io::{BufReader, Read};
 use std::collections::VecDeque;
 
 use super::action;
 use super::board;
 use super::player;
 use super::rensa_plan;
+use super::opponent;
+use super::evaluator;
 use super::replay;
-use super::consts::{W,H,MAX_TURN};
+use super::plan;
+use super::protocol;
 use super::score_calculator;
 
+use super::strategy;
 use super::rand;
 
-pub struct BestAi<T> {
+// 探索のヒープに残す状態数 (長考でもメモリを使いすぎないように)
+const HEAP_CAPACITY: usize = 100000;
+
+// exec終了時の対戦のまとめ
+#[derive(Debug, Default)]
+pub struct GameSummary {
+    pub turns: usize,
+    pub last_turn: Option<usize>,
+    pub fires: Vec<(usize, u8)>,
+}
+
+impl std::fmt::Display for GameSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let max_chains = self.fires.iter().map(|f| f.1).max().unwrap_or(0);
+        write!(f, "turns={} last_turn={:?} max_chains={} fires={:?}", self.turns, self.last_turn, max_chains, self.fires)
+    }
+}
+
+#[derive(Debug)]
+pub struct ExecError {
+    pub summary: GameSummary,
+    pub error: protocol::ProtocolError,
+}
+
+impl std::fmt::Display for ExecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.summary.last_turn {
+            Some(turn) => write!(f, "input stopped after turn {}: {}", turn, self.error),
+            None => write!(f, "input stopped before the first turn: {}", self.error),
+        }
+    }
+}
+
+impl std::error::Error for ExecError {}
+
+pub struct BestAi<T, S = BestStrategy> {
     cur_turn: usize,
 
-    // stdin_lock: StdinLock<'a>,
-    stdin_lock: T,
+    parser: protocol::Parser<BufReader<T>>,
     packs: Vec<[[u8; 2]; 2]>,
     rest_time_in_milli: usize,
     player: player::Player,
     enemy: player::Player,
+
+    strategy: S,
+    plan: replay::Replay,
+    summary: GameSummary,
+    print_summary: bool,
+}
+
+// 評価関数を使い分ける呼び出し元
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EvalSite {
+    // 連鎖を組む探索 (相手の返しの探索も含む)
+    Rensa,
+    Counter,
+    KillBommer,
+}
+
+impl EvalSite {
+    pub const ALL: [EvalSite; 3] = [EvalSite::Rensa, EvalSite::Counter, EvalSite::KillBommer];
+
+    // 設定ファイルの見出し
+    pub fn name(&self) -> &'static str {
+        match self {
+            EvalSite::Rensa => "rensa",
+            EvalSite::Counter => "do_counter",
+            EvalSite::KillBommer => "kill_bommer",
+        }
+    }
+}
+
+pub struct BestStrategy {
     rand: rand::XorShiftL,
+    num_threads: usize,
+    // (seed, 1ミリ秒あたりのノード数): 指定すると時間ではなくノード数で探索を打ち切り、結果を再現できるようにする
+    reproducible: Option<(u64, usize)>,
+    // EvalSite::ALLの順
+    evaluators: [Box<dyn evaluator::Evaluator>; 3],
 
     found_explicit_counter_turn: usize,
     maybe_bommer: bool,
     best_fire_enemy_history: VecDeque<i32>,
+    opponent: opponent::OpponentModel,
     current_best: replay::Replay,
+    plans: plan::Plans,
 }
 
 impl<U> BestAi<U> where
     U: Read
 {
     pub fn new(lock: U) -> Self {
+        Self::with_strategy(lock, BestStrategy::new())
+    }
+
+    // for test
+    pub fn rensa_search_test(&mut self) -> Vec<u8> {
+        self.read_game_input().unwrap();
+        self.read_turn_input().unwrap();
+        let s = strategy::Snapshot {
+            turn: self.cur_turn,
+            rest_time_in_milli: self.rest_time_in_milli,
+            player: &self.player,
+            enemy: &self.enemy,
+            packs: &self.packs,
+        };
+        let states = self.strategy.search_rensa(&s, EvalSite::Rensa, self.player.clone(), 13, 18000, &[]);
+        states.iter().map(|s| s.get_chains()).collect()
+    }
+
+    pub fn rensa_search_best_test(&mut self) -> replay::Replay {
+        self.read_game_input().unwrap();
+        self.read_turn_input().unwrap();
+        let s = strategy::Snapshot {
+            turn: self.cur_turn,
+            rest_time_in_milli: self.rest_time_in_milli,
+            player: &self.player,
+            enemy: &self.enemy,
+            packs: &self.packs,
+        };
+        let states = self.strategy.search_rensa(&s, EvalSite::Rensa, self.player.clone(), 13, 18000, &[]);
+        let best = self.strategy.get_best(&s, self.player.clone(), 60, &[], &states);
+        best.unwrap_or_default()
+    }
+    // end for test
+}
+
+impl<U, S> BestAi<U, S> where
+    U: Read,
+    S: strategy::Strategy
+{
+    pub fn with_strategy(lock: U, strategy: S) -> Self {
         Self {
             cur_turn: 0,
 
-            stdin_lock: lock,
+            parser: protocol::Parser::new(BufReader::new(lock)),
             packs: Vec::new(),
             rest_time_in_milli: 0,
             player: player::Player::new(board::Board::new(), 0, 0),
             enemy: player::Player::new(board::Board::new(), 0, 0),
-            rand: rand::XorShiftL::new(),
 
-            found_explicit_counter_turn: 0,
-            maybe_bommer: false,
-            best_fire_enemy_history: VecDeque::new(),
-            current_best: replay::Replay::new(),
+            strategy,
+            plan: replay::Replay::new(),
+            summary: Default::default(),
+            print_summary: false,
         }
     }
 
-    fn read1<T: FromStr>(&mut self) -> T {
-        let token = self.stdin_lock.by_ref().bytes().map(|c| c.unwrap() as char)
-            .skip_while(|c| c.is_whitespace())
-            .take_while(|c| !c.is_whitespace())
-            .collect::<String>();
-        token.parse::<T>().ok().unwrap()
+    // 終了時に標準エラーへまとめを出す
+    pub fn set_print_summary(&mut self, print_summary: bool) {
+        self.print_summary = print_summary;
     }
 
-    fn read_game_input(&mut self) {
-        (0..MAX_TURN).for_each(|_| {
-            let v1 = self.read1();
-            let v2 = self.read1();
-            let v3 = self.read1();
-            let v4 = self.read1();
-            self.read1::<String>();
-            self.packs.push([[v1, v2], [v3, v4]]);
-        });
+    fn read_game_input(&mut self) -> Result<(), protocol::ProtocolError> {
+        let input = self.parser.read_game_input()?;
+        self.packs = input.packs;
+        Ok(())
     }
 
-    fn read_board(&mut self) -> board::Board {
-        let mut board = [0; (W * H) as usize];
-        (0..W*H).for_each(|p| { board[p as usize] = self.read1::<u8>(); });
-        board::Board::from_board(board)
+    fn read_turn_input(&mut self) -> Result<(), protocol::ProtocolError> {
+        let input = self.parser.read_turn_input()?;
+        self.cur_turn = input.turn;
+        self.rest_time_in_milli = input.player.rest_time_in_milli;
+        self.player = input.player.to_player();
+        self.enemy = input.enemy.to_player();
+        Ok(())
     }
 
-    fn read_turn_input(&mut self) {
-        self.cur_turn = self.read1();
-        // eprintln!("start read {}", self.cur_turn);
-        self.rest_time_in_milli = self.read1::<usize>();
-        self.player.obstacle = self.read1();
-        self.player.skill_guage = self.read1();
-        let _player_score = self.read1::<u32>();
-        self.player.board = self.read_board();
-        self.read1::<String>();
-
-        let _rest_time_in_milli = self.read1::<u32>();
-        self.enemy.obstacle = self.read1();
-        self.enemy.skill_guage = self.read1();
-        let _enemy_score = self.read1::<u32>();
-        self.enemy.board = self.read_board();
-        self.read1::<String>();
+    // ターンの切れ目で入力が終わったら正常終了
+    pub fn exec(&mut self) -> Result<GameSummary, ExecError> {
+        println!("{}", self.strategy.name());
+        let result = self.exec_loop();
+        let summary = std::mem::take(&mut self.summary);
+        if self.print_summary {
+            eprintln!("summary: {}", summary);
+        }
+        match result {
+            Ok(()) => Ok(summary),
+            Err(error) => Err(ExecError { summary, error }),
+        }
     }
 
-    pub fn exec(&mut self) {
-        println!("test-best-ai");
-        self.read_game_input();
-        loop {
-            self.read_turn_input();
+    fn exec_loop(&mut self) -> Result<(), protocol::ProtocolError> {
+        self.read_game_input()?;
+        while !self.parser.at_eof()? {
+            self.read_turn_input()?;
             let act = self.think();
             println!("{}", act);
+
+            let result = self.player.clone().put(&self.packs[self.cur_turn], &act);
+            self.summary.turns += 1;
+            self.summary.last_turn = Some(self.cur_turn);
+            if result.chains > 0 {
+                self.summary.fires.push((self.cur_turn, result.chains));
+            }
         }
+        Ok(())
     }
 
-    // for test
-    pub fn rensa_search_test(&mut self) -> Vec<u8> {
-        self.read_game_input();
-        self.read_turn_input();
-        let states = self.search_rensa(self.player.clone(), 13, 18000, &[]);
-        states.iter().map(|s| s.get_chains()).collect()
+    // 戦略が何も決めなければ以前に採用した計画を続ける
+    fn think(&mut self) -> action::Action {
+        let s = strategy::Snapshot {
+            turn: self.cur_turn,
+            rest_time_in_milli: self.rest_time_in_milli,
+            player: &self.player,
+            enemy: &self.enemy,
+            packs: &self.packs,
+        };
+        match self.strategy.think(&s) {
+            Some(strategy::Decision::Action(a)) => return a,
+            Some(strategy::Decision::Plan(plan)) => self.plan = plan,
+            None => {},
+        }
+
+        if self.plan.can_replay(&self.player, &[]) {
+            self.plan.replay().unwrap()
+        } else {
+            BestStrategy::resign()
+        }
     }
+}
 
-    pub fn rensa_search_best_test(&mut self) -> replay::Replay {
-        self.read_game_input();
-        self.read_turn_input();
-        let states = self.search_rensa(self.player.clone(), 13, 18000, &[]);
-        let best = self.get_best(self.player.clone(), 60, &[], &states);
-        best.unwrap_or(replay::Replay::new())
+impl BestStrategy {
+    pub fn new() -> Self {
+        Self {
+            rand: rand::XorShiftL::new(),
+            num_threads: rensa_plan::available_threads(),
+            reproducible: None,
+            evaluators: EvalSite::ALL.map(|_| Box::new(evaluator::LinearEvaluator::default()) as Box<dyn evaluator::Evaluator>),
+
+            found_explicit_counter_turn: 0,
+            maybe_bommer: false,
+            best_fire_enemy_history: VecDeque::new(),
+            opponent: Default::default(),
+            current_best: replay::Replay::new(),
+            plans: plan::Plans::new(4),
+        }
     }
-    // end for test
 
-    fn think(&mut self) -> action::Action {
+    // 試す順に並べた分岐なので中身は空で良い
+    #[allow(clippy::if_same_then_else)]
+    fn decide(&mut self, s: &strategy::Snapshot) -> action::Action {
+        self.opponent.observe(s);
+
         // for bommer
-        if self.cur_turn == 10 && self.enemy.skill_guage >= 30 {
+        if s.turn == 10 && (s.enemy.skill_guage >= 30 || self.opponent.playstyle() == opponent::Playstyle::Bomber) {
             self.maybe_bommer = true;
         }
 
         if self.maybe_bommer {
-            return self.kill_bommer();
+            return self.kill_bommer(s);
         }
 
-        self.best_fire_enemy_history.push_back(self.fire(&self.enemy).2);
+        self.best_fire_enemy_history.push_back(self.fire(s, s.enemy).2);
         if self.best_fire_enemy_history.len() > 5 {
             self.best_fire_enemy_history.pop_front();
         }
 
         if false {
-        } else if self.do_counter() {
-        } else if self.rensa() {
-        } else if self.do_anti_counter() {
-        } else if self.anti_counter_kera() {
+        } else if self.do_counter(s) {
+        } else if self.rensa(s) {
+        } else if self.do_anti_counter(s) {
+        } else if self.anti_counter_kera(s) {
         }
 
         if self.current_best.is_empty() {
@@ -151,201 +289,265 @@ impl<U> BestAi<U> where
         }
     }
 
-    fn rensa_extend(&mut self, max_turn: usize, think_time_in_milli: u64) {
+    fn rensa_extend(&mut self, s: &strategy::Snapshot, max_turn: usize, think_time_in_milli: u64) {
         let limit = 10000000;
         let enemy_send_obstacles = vec![];
 
-        let states = self.search_rensa(self.player.clone(), max_turn, think_time_in_milli, &enemy_send_obstacles);
+        let states = self.search_rensa_against_enemy(s, max_turn, think_time_in_milli, rensa_plan::Aggregation::Expectation);
 
-        let best = self.get_best(self.player.clone(), limit, &enemy_send_obstacles, &states);
+        let best = self.get_best(s, s.player.clone(), limit, &enemy_send_obstacles, &states);
         if let Some(best) = best {
             self.current_best = best;
             let fire = states.iter().map(|r| r.get_chains()).collect::<Vec<_>>();
-            eprintln!("extend done: {} {} {:?}", self.cur_turn, self.current_best.get_actions().len(), fire);
+            eprintln!("extend done: {} {} {:?}", s.turn, self.current_best.get_actions().len(), fire);
         }
     }
 
-    fn rensa(&mut self) -> bool {
-        if self.current_best.can_replay(&self.player, &[]) {
+    fn rensa(&mut self, s: &strategy::Snapshot) -> bool {
+        if self.current_best.can_replay(s.player, &[]) {
             return false;
         }
 
-        let max_turn = if self.cur_turn <= 10 { 13 } else { 10 };
-        let mut think_time_in_milli = if self.cur_turn <= 10 { 18000 } else { 15000 };
+        let max_turn = if s.turn <= 10 { 13 } else { 10 };
+        let mut think_time_in_milli = if s.turn <= 10 { 18000 } else { 15000 };
         let limit = 60;
         let enemy_send_obstacles = vec![];
 
-        if self.rest_time_in_milli < 30 * 1000 {
+        // 探し直す前に、以前の探索で見つけた他の計画がまだ使えないか見る
+        if self.replay_stored_plan(s, limit) {
+            return true;
+        }
+        if s.rest_time_in_milli < 30 * 1000 {
             // emergency
             think_time_in_milli = 1000;
         }
 
-        let states = self.search_rensa(self.player.clone(), max_turn, think_time_in_milli, &enemy_send_obstacles);
+        let states = self.search_rensa_against_enemy(s, max_turn, think_time_in_milli, rensa_plan::Aggregation::Expectation);
+        self.plans.extend(&states);
 
-        let best = self.get_best(self.player.clone(), limit, &enemy_send_obstacles, &states);
+        let best = self.get_best(s, s.player.clone(), limit, &enemy_send_obstacles, &states);
         if let Some(best) = best {
             self.current_best = best;
             let fire = states.iter().map(|r| r.get_chains()).collect::<Vec<_>>();
-            eprintln!("think done: {} {} {:?}", self.cur_turn, self.current_best.get_actions().len(), fire);
+            eprintln!("think done: {} {} {:?}", s.turn, self.current_best.get_actions().len(), fire);
+        } else {
+            self.replay_stored_plan(s, limit);
         }
         true
     }
 
-    fn do_counter(&mut self) -> bool {
-        if self.rest_time_in_milli < 30 * 1000 {
+    // 相手の打ち方の予想 (他の戦略からも使う)
+    pub fn opponent(&self) -> &opponent::OpponentModel {
+        &self.opponent
+    }
+
+    pub fn set_evaluator<E: evaluator::Evaluator + 'static>(&mut self, site: EvalSite, evaluator: E) {
+        self.evaluators[site as usize] = Box::new(evaluator);
+    }
+
+    // 全部の呼び出し元の重みを設定ファイルから読む
+    pub fn load_weights<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<(), evaluator::ConfigError> {
+        let text = std::fs::read_to_string(path)?;
+        for site in EvalSite::ALL {
+            self.set_evaluator(site, evaluator::LinearEvaluator::from_config(&text, site.name())?);
+        }
+        Ok(())
+    }
+
+    pub fn set_num_threads(&mut self, num_threads: usize) {
+        self.num_threads = num_threads;
+    }
+
+    // 同じ入力なら常に同じ手を返すようにする (1スレッドで探索する)
+    pub fn set_reproducible(&mut self, seed: u64, nodes_per_milli: usize) {
+        self.reproducible = Some((seed, nodes_per_milli));
+    }
+
+    // 以前の探索で見つけた計画のうちまだ再生できるものを使う
+    fn replay_stored_plan(&mut self, s: &strategy::Snapshot, limit_obstacle: i32) -> bool {
+        let limit_chains = score_calculator::ScoreCalculator::lower_bound(limit_obstacle);
+        match self.plans.best(s.player, &[], limit_chains) {
+            // 連鎖しない計画しか残っていなければ探し直す
+            Some(best) if best.get_chains() > 0 => {
+                self.current_best = best.clone();
+                eprintln!("stored plan: {} {} {}", s.turn, self.current_best.len(), self.current_best.get_chains());
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn do_counter(&mut self, s: &strategy::Snapshot) -> bool {
+        if s.rest_time_in_milli < 30 * 1000 {
             return false;
         }
         let enemy_attack = *self.best_fire_enemy_history.back().unwrap();
         let n = self.best_fire_enemy_history.len();
         let max_enemy_attack = *self.best_fire_enemy_history.iter().take(n - 1).max().unwrap_or(&0);
-        let threshold = if self.cur_turn < 15 { 40 } else { 30 };
+        let threshold = if s.turn < 15 { 40 } else { 30 };
         if enemy_attack < threshold || max_enemy_attack >= enemy_attack {
             return false
         }
-        let self_counter_states = self.search_rensa(self.player.clone(), 10, 15000, &[enemy_attack]);
-        if let Some(best_counter) = self.get_best(self.player.clone(), enemy_attack * 3 / 2, &[enemy_attack], &self_counter_states) {
+        // 今すぐ撃たれる場合と1手遅れて撃たれる場合のどちらでも死なない手を探す
+        let later = [0, enemy_attack];
+        let scenarios = [
+            rensa_plan::ObstacleScenario { weight: 1, obstacles: &[enemy_attack] },
+            rensa_plan::ObstacleScenario { weight: 1, obstacles: &later },
+        ];
+        let self_counter_states = self.search_rensa_with_scenarios(s, EvalSite::Counter, s.player.clone(), 10, 15000, &scenarios, rensa_plan::Aggregation::WorstCase);
+        if let Some(best_counter) = self.get_best(s, s.player.clone(), enemy_attack * 3 / 2, &[enemy_attack], &self_counter_states) {
             self.current_best = best_counter;
             let fire = self_counter_states.iter().map(|r| r.get_chains()).collect::<Vec<_>>();
-            eprintln!("counter done: {} {} {:?}", self.cur_turn, self.current_best.get_actions().len(), fire);
+            eprintln!("counter done: {} {} {:?}", s.turn, self.current_best.get_actions().len(), fire);
         }
         true
     }
 
-    fn do_anti_counter(&mut self) -> bool {
+    fn do_anti_counter(&mut self, s: &strategy::Snapshot) -> bool {
         let limit = 10000000;
-        if self.rest_time_in_milli < 30 * 1000 {
+        if s.rest_time_in_milli < 30 * 1000 {
             return false;
         }
         if self.current_best.len() != 1 {
             return false;
         }
 
-        let my_attack = self.fire(&self.player);
-        let enemy_counter_states = self.search_rensa(self.enemy.clone(), 7, 5000, &[my_attack.2]);
-        if let Some(enemy_counter_best) = self.get_best(self.enemy.clone(), limit, &[my_attack.2], &enemy_counter_states) {
-            if enemy_counter_best.get_chains() >= my_attack.1.chains + 1 {
-                self.rensa_extend(8, 13000);
+        let my_attack = self.fire(s, s.player);
+        let enemy_counter_states = self.search_rensa(s, EvalSite::Rensa, s.enemy.clone(), 7, 5000, &[my_attack.2]);
+        if let Some(enemy_counter_best) = self.get_best(s, s.enemy.clone(), limit, &[my_attack.2], &enemy_counter_states) {
+            if enemy_counter_best.get_chains() > my_attack.1.chains {
+                self.rensa_extend(s, 8, 13000);
             }
         }
         true
     }
 
-    fn enemy_counter_result(&self) -> (bool, action::ActionResult) {
-        let (_, result, (x,y)) = self.enemy.board.calc_max_rensa_by_erase_block();
-        // eprintln!("anticounter: {} {}", y, self.enemy.board.adjust_height_min(x));
+    fn enemy_counter_result(&self, s: &strategy::Snapshot) -> (bool, action::ActionResult) {
+        let (_, result, (x,y)) = s.enemy.board.calc_max_rensa_by_erase_block();
+        // eprintln!("anticounter: {} {}", y, s.enemy.board.adjust_height_min(x));
         // unreachable!();
-        let dy = y as i32 - self.enemy.board.adjust_height_min(x) as i32;
-        (dy >= 5 && dy <= 8, result)
+        let dy = y as i32 - s.enemy.board.adjust_height_min(x) as i32;
+        ((5..=8).contains(&dy), result)
     }
 
-    fn anti_counter_kera(&mut self) -> bool {
-        let (is_counter, result) = self.enemy_counter_result();
+    fn anti_counter_kera(&mut self, s: &strategy::Snapshot) -> bool {
+        let (is_counter, result) = self.enemy_counter_result(s);
         if !is_counter || self.current_best.get_chains() >= result.chains + 2 {
             return false;
         }
-        if self.rest_time_in_milli < 30 * 1000 {
+        if s.rest_time_in_milli < 30 * 1000 {
             return false;
         }
 
-        self.found_explicit_counter_turn = self.cur_turn;
+        self.found_explicit_counter_turn = s.turn;
         self.current_best.clear();
         let max_turn = 8;
         let think_time_in_milli = 15000;
         let limit = 10000000;
         let enemy_send_obstacles = vec![];
 
-        let states = self.search_rensa(self.player.clone(), max_turn, think_time_in_milli, &enemy_send_obstacles);
+        // 相手の返しを警戒している場面なので、どの想定でも崩れない連鎖を選ぶ
+        let states = self.search_rensa_against_enemy(s, max_turn, think_time_in_milli, rensa_plan::Aggregation::WorstCase);
 
-        let best = self.get_best(self.player.clone(), limit, &enemy_send_obstacles, &states);
+        let best = self.get_best(s, s.player.clone(), limit, &enemy_send_obstacles, &states);
         if let Some(best) = best {
             self.current_best = best;
             let fire = states.iter().map(|r| r.get_chains()).collect::<Vec<_>>();
-            eprintln!("anti_counter done: {} {} {:?}", self.cur_turn, self.current_best.get_actions().len(), fire);
+            eprintln!("anti_counter done: {} {} {:?}", s.turn, self.current_best.get_actions().len(), fire);
         }
 
         true
     }
 
-    fn kill_bommer(&mut self) -> action::Action {
-        if self.cur_turn != 10 && self.current_best.can_replay(&self.player, &[]) {
+    fn kill_bommer(&mut self, s: &strategy::Snapshot) -> action::Action {
+        if s.turn != 10 && self.current_best.can_replay(s.player, &[]) {
             return self.current_best.replay().unwrap();
         }
 
-        let max_turn = if self.cur_turn <= 10 { 8 } else { 11 };
+        let max_turn = if s.turn <= 10 { 8 } else { 11 };
         let mut think_time_in_milli = 15000;
         let limit = 200;
         let enemy_send_obstacles = vec![0; max_turn];
 
-        if self.rest_time_in_milli < 30 * 1000 {
+        if s.rest_time_in_milli < 30 * 1000 {
             think_time_in_milli = 1000;
         }
 
-        let states = self.search_rensa(self.player.clone(), max_turn, think_time_in_milli, &enemy_send_obstacles);
-        let best = self.get_best(self.player.clone(), limit, &enemy_send_obstacles, &states);
+        let states = self.search_rensa(s, EvalSite::KillBommer, s.player.clone(), max_turn, think_time_in_milli, &enemy_send_obstacles);
+        let best = self.get_best(s, s.player.clone(), limit, &enemy_send_obstacles, &states);
         if let Some(best) = best {
             self.current_best = best;
-            eprintln!("think done bommer: {} {} {}", self.cur_turn, self.current_best.get_actions().len(), self.current_best.get_obstacles(&self.player).last().unwrap());
+            eprintln!("think done bommer: {} {} {}", s.turn, self.current_best.get_actions().len(), self.current_best.get_obstacles(s.player).last().unwrap());
             self.current_best.replay().unwrap()
         } else {
             Self::resign()
         }
     }
 
-    fn search_rensa(&mut self, player: player::Player, max_turn: usize, think_time_in_milli: u64, enemy_send_obstacles: &[i32]) -> Vec<replay::Replay> {
-        let context = rensa_plan::PlanContext {
-            plan_start_turn: self.cur_turn,
+    fn search_rensa(&mut self, s: &strategy::Snapshot, site: EvalSite, player: player::Player, max_turn: usize, think_time_in_milli: u64, enemy_send_obstacles: &[i32]) -> Vec<replay::Replay> {
+        let scenarios = [rensa_plan::ObstacleScenario { weight: 1, obstacles: enemy_send_obstacles }];
+        self.search_rensa_with_scenarios(s, site, player, max_turn, think_time_in_milli, &scenarios, rensa_plan::Aggregation::WorstCase)
+    }
+
+    // 相手の予想から作ったお邪魔の想定で自分の連鎖を探す。返す計画は撃たれない想定で再生したもの
+    // 連鎖を伸ばすときはExpectation、最悪の場合を外せないときはWorstCaseを使う
+    // (WorstCaseだと予想が外れやすい序盤でも撃たれる想定の評価値しか見なくなり、連鎖が伸びにくい)
+    fn search_rensa_against_enemy(&mut self, s: &strategy::Snapshot, max_turn: usize, think_time_in_milli: u64, aggregation: rensa_plan::Aggregation) -> Vec<replay::Replay> {
+        let scenarios = self.opponent.scenarios();
+        let scenarios = scenarios.iter().map(Into::into).collect::<Vec<_>>();
+        self.search_rensa_with_scenarios(s, EvalSite::Rensa, s.player.clone(), max_turn, think_time_in_milli, &scenarios, aggregation)
+    }
+
+    // お邪魔の降り方を複数想定して探す。返す計画はscenarios[0]で再生したもの
+    #[allow(clippy::too_many_arguments)]
+    fn search_rensa_with_scenarios(&mut self, s: &strategy::Snapshot, site: EvalSite, player: player::Player, max_turn: usize, think_time_in_milli: u64, scenarios: &[rensa_plan::ObstacleScenario], aggregation: rensa_plan::Aggregation) -> Vec<replay::Replay> {
+        let mut context = rensa_plan::PlanContext {
+            plan_start_turn: s.turn,
             max_turn,
-            think_time_in_milli: think_time_in_milli,
+            think_time_in_milli,
             player,
-            enemy_send_obstacles,
-            packs: &self.packs,
+            enemy_send_obstacles: scenarios[0].obstacles,
+            packs: s.packs,
+            num_threads: self.num_threads,
+            heap_capacity: HEAP_CAPACITY,
+            scenarios,
+            aggregation,
+            ..Default::default()
         };
+        if let Some((seed, nodes_per_milli)) = self.reproducible {
+            context.num_threads = 1;
+            context.max_nodes = Some(think_time_in_milli as usize * nodes_per_milli);
+            context.seed = Some(seed);
+        }
+
+        let evaluator = &self.evaluators[site as usize];
+        rensa_plan::calc_rensa_plan(&context, &mut self.rand, |result, player, feature| evaluator.evaluate(result, player, feature))
+    }
 
-        rensa_plan::calc_rensa_plan(&context, &mut self.rand, |result, player, feature| {
-            let obstacle_score = std::cmp::min(result.obstacle, 200);
-            let max_height = (std::cmp::max(H - 2, player.board.max_height()) - (H - 2)) as i32;
-            // let feature_score =
-            //                     feature.pairX * 30000
-            //                     + feature.pair5 * 2000
-            //                     + feature.num_block * 20
-            //                     ;
-            let feature_score =
-                                (result.fire_height as i32) * 1000
-                                - max_height * 10000
-                                + feature.keima * 50
-                                + feature.tate * 40
-                                + feature.keima2 * 1
-                                + feature.tate2 * 1
-                                + feature.num_block * 2000
-                                ;
-            obstacle_score as i64 * 1000000 + feature_score as i64
-        })
-    }
-
-    fn get_best(&self, player: player::Player, limit_obstacle: i32, enemy_send_obstacles: &[i32], states: &[replay::Replay]) -> Option<replay::Replay> {
+    fn get_best(&self, s: &strategy::Snapshot, player: player::Player, limit_obstacle: i32, enemy_send_obstacles: &[i32], states: &[replay::Replay]) -> Option<replay::Replay> {
         let mut max = -1;
         let mut choosed: Option<&replay::Replay> = None;
         let limit_chains = score_calculator::ScoreCalculator::lower_bound(limit_obstacle);
         // eprintln!("get_best: {} {}", limit_obstacle, limit_chains);
-        states.iter().for_each(|s| {
-            let val = std::cmp::min(limit_chains, s.get_chains() as i32);
+        states.iter().for_each(|state| {
+            let val = std::cmp::min(limit_chains, state.get_chains() as i32);
             if max < val {
                 max = val;
-                choosed = Some(s);
+                choosed = Some(state);
             }
         });
 
         let mut replay = replay::Replay::new();
         match choosed {
             None => None,
-            Some(s) => {
-                let actions = s.get_actions();
+            Some(state) => {
+                let actions = state.get_actions();
 
                 if actions.is_empty() {
                     None
                 } else {
-                    let last_turn = self.cur_turn + actions.len();
-                    let packs = &self.packs[self.cur_turn..last_turn];
+                    let last_turn = s.turn + actions.len();
+                    let packs = &s.packs[s.turn..last_turn];
                     replay.init(&player, packs, enemy_send_obstacles, &actions);
                     Some(replay)
                 }
@@ -353,9 +555,9 @@ impl<U> BestAi<U> where
         }
     }
 
-    fn fire(&self, player: &player::Player) -> (action::Action, action::ActionResult, i32) {
+    fn fire(&self, s: &strategy::Snapshot, player: &player::Player) -> (action::Action, action::ActionResult, i32) {
         let actions = action::Action::all_actions();
-        let pack = self.packs[self.cur_turn];
+        let pack = s.packs[s.turn];
         actions.iter().map(|a| {
             if &action::Action::UseSkill == a && !player.can_use_skill() {
                 return (action::Action::UseSkill, Default::default(), 0);
@@ -371,3 +573,71 @@ impl<U> BestAi<U> where
         action::Action::PutBlock { pos: 0, rot: 0, }
     }
 }
+
+impl strategy::Strategy for BestStrategy {
+    fn think(&mut self, s: &strategy::Snapshot) -> Option<strategy::Decision> {
+        let action = self.decide(s);
+        self.plans.advance(&action);
+        Some(strategy::Decision::Action(action))
+    }
+}
+
+impl Default for BestStrategy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn best_ai_test_exec_eof() {
+    let mut input = Vec::new();
+    protocol::GameInput { packs: vec![[[1, 2], [3, 4]]; super::consts::MAX_TURN] }.write(&mut input).unwrap();
+
+    let summary = BestAi::new(&input[..]).exec().unwrap();
+    assert_eq!(summary.turns, 0);
+    assert_eq!(summary.last_turn, None);
+
+    input.extend_from_slice(b"0\n180000\n");
+    let e = BestAi::new(&input[..]).exec().unwrap_err();
+    assert!(e.error.is_eof());
+    assert_eq!(e.summary.last_turn, None);
+}
+
+#[test]
+fn best_ai_test_rensa_against_enemy() {
+    let packs = vec![[[7, 0], [0, 0]]; super::consts::MAX_TURN];
+    let player = player::Player::default();
+    // 相手はすぐに2連鎖を撃てる
+    let enemy = player::Player::new(board::board_test_rensa2(), 0, 0);
+    let s = strategy::Snapshot { turn: 0, rest_time_in_milli: 180000, player: &player, enemy: &enemy, packs: &packs };
+    let mut strategy = BestStrategy::new();
+    strategy.set_reproducible(1, 10);
+    strategy.opponent.observe(&s);
+    let scenarios = strategy.opponent().scenarios();
+    assert!(scenarios.len() > 1 && scenarios[0].obstacles.is_empty());
+
+    // 撃たれる想定でも探すが、計画は撃たれない想定で再生できる
+    let states = strategy.search_rensa_against_enemy(&s, 3, 100, rensa_plan::Aggregation::WorstCase);
+    assert!(states.iter().any(|r| !r.is_empty()));
+    assert!(states.iter().filter(|r| !r.is_empty()).all(|r| r.can_replay(&player, &[])));
+}
+
+#[test]
+fn best_ai_test_stored_plan() {
+    let packs = vec![[[7, 0], [0, 0]]; super::consts::MAX_TURN];
+    let player = player::Player::new(board::board_test_rensa2(), 0, 0);
+    let enemy = player::Player::default();
+    let s = strategy::Snapshot { turn: 0, rest_time_in_milli: 180000, player: &player, enemy: &enemy, packs: &packs };
+    let put = |pos| action::Action::PutBlock { pos, rot: 0 };
+    let mut stored = replay::Replay::new();
+    stored.init(&player, &packs[..2], &[], &[put(5), put(2)]);
+    assert_eq!(stored.get_chains(), 2);
+
+    // 今の計画が再生できなくなったら、探索する前に覚えている計画を使う
+    let mut strategy = BestStrategy::new();
+    strategy.plans.insert(stored);
+    assert!(strategy.rensa(&s));
+    assert_eq!(strategy.current_best.get_actions(), vec![put(5), put(2)]);
+    // 再生できるうちは何もしない
+    assert!(!strategy.rensa(&s));
+}
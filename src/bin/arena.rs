@@ -0,0 +1,293 @@
+// ローカル対戦用
+// usage: arena [--games N] [--seed S] [--packs FILE | --pack-dist FILE] [--save-packs FILE] [--record FILE] [--time MS] [--stderr] <ai1> <ai2>
+//        arena --ai best|rensa|bomber [--weights FILE]
+// パックは--packsのファイルか、なければseed+試合番号から作る
+// --pack-distには実際の試合のパックファイルを渡し、その分布に合わせて作る。どちらも無ければ仮の分布を使う (勝率は公式の試合と比べられない)
+// --recordで試合の記録を保存する (拡張子が.jsonならJSON)
+// aiにはシェルコマンドか、このバイナリ自身を起動する @best / @rensa / @bomber を指定する
+// @best:FILE のようにするとFILEから評価関数の重みを読む (重みの比較用)
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use codevs_reborn_lib::best_ai::{BestAi, BestStrategy};
+use codevs_reborn_lib::bomber::BomberStrategy;
+use codevs_reborn_lib::game::{Game, Outcome};
+use codevs_reborn_lib::pack;
+use codevs_reborn_lib::record::GameRecord;
+use codevs_reborn_lib::rensa_ai::{RensaAi, RensaStrategy};
+use codevs_reborn_lib::protocol::{GameInput, Parser, PlayerInput, TurnInput};
+use codevs_reborn_lib::rand::XorShiftL;
+use codevs_reborn_lib::types::RawPack;
+
+const TIME_LIMIT_IN_MILLI: u64 = 180 * 1000;
+
+struct Config {
+    games: usize,
+    seed: u64,
+    packs: Option<String>,
+    pack_dist: Option<String>,
+    save_packs: Option<String>,
+    record: Option<String>,
+    time_limit_in_milli: u64,
+    show_stderr: bool,
+    ais: Vec<String>,
+}
+
+struct AiProcess {
+    child: Child,
+    stdin: ChildStdin,
+    lines: mpsc::Receiver<(Instant, String)>,
+    rest_time_in_milli: u64,
+}
+
+impl AiProcess {
+    fn spawn(spec: &str, config: &Config) -> std::io::Result<Self> {
+        let mut command = if let Some(ai) = spec.strip_prefix('@') {
+            let mut command = Command::new(std::env::current_exe()?);
+            match ai.split_once(':') {
+                Some((ai, weights)) => command.arg("--ai").arg(ai).arg("--weights").arg(weights),
+                None => command.arg("--ai").arg(ai),
+            };
+            command
+        } else {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(spec);
+            command
+        };
+        let stderr = if config.show_stderr { Stdio::inherit() } else { Stdio::null() };
+        let mut child = command.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(stderr).spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let line = match line { Ok(line) => line, Err(_) => break };
+                if sender.send((Instant::now(), line)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            child,
+            stdin,
+            lines,
+            rest_time_in_milli: config.time_limit_in_milli,
+        })
+    }
+
+    // 持ち時間内に1行読む。時間切れかプロセス終了ならNone
+    fn read_line(&mut self, sent: Instant) -> Option<String> {
+        let (received, line) = self.lines.recv_timeout(Duration::from_millis(self.rest_time_in_milli)).ok()?;
+        let elapsed = received.saturating_duration_since(sent).as_millis() as u64;
+        if elapsed > self.rest_time_in_milli {
+            return None;
+        }
+        self.rest_time_in_milli -= elapsed;
+        Some(line)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> bool {
+        self.stdin.write_all(buf).and_then(|_| self.stdin.flush()).is_ok()
+    }
+
+    fn kill(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[derive(Default)]
+struct Stats {
+    win: usize,
+    lose: usize,
+    draw: usize,
+    max_chains: Vec<u8>,
+    // 連鎖した試合だけの、最大連鎖を撃ったターン
+    fire_turns: Vec<usize>,
+}
+
+impl Stats {
+    fn average<T: Copy + Into<f64>>(v: &[T]) -> f64 {
+        if v.is_empty() { 0.0 } else { v.iter().map(|x| (*x).into()).sum::<f64>() / v.len() as f64 }
+    }
+}
+
+fn turn_input(game: &Game, ais: &[AiProcess; 2], side: usize) -> Vec<u8> {
+    let player_input = |i: usize| PlayerInput::from_player(game.player(i), ais[i].rest_time_in_milli as usize, game.score(i));
+    let input = TurnInput { turn: game.turn(), player: player_input(side), enemy: player_input(1 - side) };
+    let mut buf = Vec::new();
+    input.write(&mut buf).unwrap();
+    buf
+}
+
+fn play(config: &Config, packs: Vec<RawPack>, stats: &mut [Stats; 2]) -> std::io::Result<GameRecord> {
+    let mut ais = [AiProcess::spawn(&config.ais[0], config)?, AiProcess::spawn(&config.ais[1], config)?];
+    let mut record = GameRecord::new(packs.clone());
+    let mut game = Game::new(packs);
+    let mut max_chains = [(0, 0); 2];
+
+    let mut input = Vec::new();
+    GameInput { packs: game.packs().to_vec() }.write(&mut input)?;
+    let now = Instant::now();
+    let ok = [0, 1].map(|i| ais[i].write(&input) && ais[i].read_line(now).is_some());
+    if !ok[0] || !ok[1] {
+        game.forfeit([!ok[0], !ok[1]]);
+    }
+
+    while !game.is_over() {
+        let turn = game.turn();
+        let inputs = [0, 1].map(|i| turn_input(&game, &ais, i));
+        let now = Instant::now();
+        let written = [0, 1].map(|i| ais[i].write(&inputs[i]));
+        let actions = [0, 1].map(|i| if written[i] { ais[i].read_line(now).and_then(|l| Parser::new(l.as_bytes()).read_action().ok()) } else { None });
+
+        match &actions {
+            [Some(a0), Some(a1)] => {
+                let results = record.step(&mut game, [a0, a1]);
+                (0..2).for_each(|i| {
+                    if results[i].chains > max_chains[i].0 {
+                        max_chains[i] = (results[i].chains, turn);
+                    }
+                });
+            },
+            _ => game.forfeit([actions[0].is_none(), actions[1].is_none()]),
+        }
+    }
+    ais.iter_mut().for_each(|ai| ai.kill());
+    record.finish(&game);
+
+    let outcome = game.outcome().unwrap();
+    (0..2).for_each(|i| {
+        match outcome {
+            Outcome::Win(w) if w == i => stats[i].win += 1,
+            Outcome::Win(_) => stats[i].lose += 1,
+            Outcome::Draw => stats[i].draw += 1,
+        }
+        stats[i].max_chains.push(max_chains[i].0);
+        // 一度も連鎖しなかった試合は発火ターンの平均に入れない
+        if max_chains[i].0 > 0 {
+            stats[i].fire_turns.push(max_chains[i].1);
+        }
+    });
+    eprintln!("turn={} outcome={:?} score={} {} max_chains={:?}", game.turn(), outcome, game.score(0), game.score(1), max_chains);
+    Ok(record)
+}
+
+// 複数試合なら試合番号を付けて別々に保存する
+fn numbered_path(config: &Config, path: &str, game: usize) -> String {
+    if config.games > 1 { format!("{}.{}", path, game) } else { path.to_string() }
+}
+
+fn run_ai(name: &str, weights: Option<&str>) {
+    let stdin = std::io::stdin();
+    let result = match name {
+        "best" => {
+            let mut strategy = BestStrategy::new();
+            if let Some(path) = weights {
+                if let Err(e) = strategy.load_weights(path) {
+                    eprintln!("{}: {}", path, e);
+                    std::process::exit(2);
+                }
+            }
+            let mut ai = BestAi::with_strategy(stdin.lock(), strategy);
+            ai.set_print_summary(true);
+            ai.exec().map(|_| ())
+        },
+        "bomber" => {
+            let mut ai = BestAi::with_strategy(stdin.lock(), BomberStrategy::new());
+            ai.set_print_summary(true);
+            ai.exec().map(|_| ())
+        },
+        "rensa" => {
+            let mut ai = RensaAi::with_strategy(stdin.lock(), RensaStrategy::new());
+            ai.set_print_summary(true);
+            ai.exec().map(|_| ())
+        },
+        _ => panic!("unknown ai: {}", name),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn parse_args() -> Config {
+    let mut config = Config {
+        games: 1,
+        seed: 1,
+        packs: None,
+        pack_dist: None,
+        save_packs: None,
+        record: None,
+        time_limit_in_milli: TIME_LIMIT_IN_MILLI,
+        show_stderr: false,
+        ais: Vec::new(),
+    };
+    let mut ai = None;
+    let mut weights = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ai" => ai = Some(args.next().expect("--ai needs a name")),
+            "--weights" => weights = Some(args.next().expect("--weights needs a file")),
+            "--games" => config.games = args.next().and_then(|v| v.parse().ok()).expect("--games needs a number"),
+            "--seed" => config.seed = args.next().and_then(|v| v.parse().ok()).expect("--seed needs a number"),
+            "--packs" => config.packs = Some(args.next().expect("--packs needs a file")),
+            "--pack-dist" => config.pack_dist = Some(args.next().expect("--pack-dist needs a file")),
+            "--save-packs" => config.save_packs = Some(args.next().expect("--save-packs needs a file")),
+            "--record" => config.record = Some(args.next().expect("--record needs a file")),
+            "--time" => config.time_limit_in_milli = args.next().and_then(|v| v.parse().ok()).expect("--time needs a number"),
+            "--stderr" => config.show_stderr = true,
+            _ => config.ais.push(arg),
+        }
+    }
+    if let Some(ai) = ai {
+        run_ai(&ai, weights.as_deref());
+        std::process::exit(0);
+    }
+    if config.ais.len() != 2 {
+        eprintln!("usage: arena [--games N] [--seed S] [--packs FILE | --pack-dist FILE] [--save-packs FILE] [--record FILE] [--time MS] [--stderr] <ai1> <ai2>");
+        std::process::exit(2);
+    }
+    config
+}
+
+fn main() -> std::io::Result<()> {
+    let config = parse_args();
+    let mut stats: [Stats; 2] = Default::default();
+    let load = |path: &String| pack::load(path).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{}: {}", path, e)));
+    let loaded = match &config.packs {
+        Some(path) => Some(load(path)?),
+        None => None,
+    };
+    let dist = match &config.pack_dist {
+        Some(path) => pack::PackDistribution::fit(&load(path)?),
+        None => {
+            if loaded.is_none() {
+                eprintln!("warning: using synthetic packs; pass --packs or --pack-dist with packs from a real game for representative results");
+            }
+            pack::PackDistribution::SYNTHETIC
+        },
+    };
+    for game in 0..config.games {
+        let packs = loaded.clone().unwrap_or_else(|| dist.generate(&mut XorShiftL::from_seed(config.seed.wrapping_add(game as u64))));
+        if let Some(path) = &config.save_packs {
+            pack::save(numbered_path(&config, path, game), &packs)?;
+        }
+        let record = play(&config, packs, &mut stats)?;
+        if let Some(path) = &config.record {
+            record.save(numbered_path(&config, path, game))?;
+        }
+    }
+
+    (0..2).for_each(|i| {
+        let s = &stats[i];
+        println!("{}: win={} lose={} draw={} avg_max_chains={:.2} avg_fire_turn={:.2}",
+            config.ais[i], s.win, s.lose, s.draw, Stats::average(&s.max_chains), Stats::average(&s.fire_turns.iter().map(|t| *t as u32).collect::<Vec<_>>()));
+    });
+    Ok(())
+}
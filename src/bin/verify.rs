@@ -0,0 +1,27 @@
+// 試合の記録を再計算して、記録された盤面や結果と一致するか調べる
+// usage: verify <record>...
+use codevs_reborn_lib::record::GameRecord;
+
+fn main() {
+    let paths = std::env::args().skip(1).collect::<Vec<_>>();
+    if paths.is_empty() {
+        eprintln!("usage: verify <record>...");
+        std::process::exit(2);
+    }
+
+    let mut failed = 0;
+    for path in paths.iter() {
+        let result = GameRecord::load(path).map_err(|e| e.to_string())
+            .and_then(|record| record.verify().map(|_| record.turns.len()).map_err(|d| d.to_string()));
+        match result {
+            Ok(turns) => println!("{}: ok ({} turns)", path, turns),
+            Err(e) => {
+                failed += 1;
+                println!("{}: {}", path, e);
+            },
+        }
+    }
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
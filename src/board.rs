@@ -2,27 +2,115 @@
 
 
 use super::action;
+use super::rand;
 use super::score_calculator;
 
 use super::consts::{W,H,VANISH,OBSTACLE};
 
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct Feature {
     pub keima: i32,
     pub keima2: i32,
     pub tate: i32,
     pub tate2: i32,
     pub num_block: i32,
+    // 1列空けて横に並んだ足して10の組
+    pub yoko: i32,
+    // 1列空けて2段ずれた足して10の組
+    pub naname: i32,
+    // 高さの分散のW*W倍
+    pub height_var: i32,
+    // 隣の列との高さの差の和
+    pub bumpiness: i32,
+    // スキルで消える5の数
+    pub fives: i32,
+    // 列ごとのお邪魔の段数
+    pub obstacle_rows: [i32; W],
+    // 一番上のお邪魔より下にある数字ブロックの数
+    pub buried: i32,
+    // calc_max_rensa_by_erase_blockの連鎖数
+    pub potential_chains: i32,
+}
+
+// スキルを使ったときの特徴
+pub struct BombFeature {
+    // 盤面の5の数
+    pub fives: i32,
+    // 今スキルを使うと消えるブロック数
+    pub bombed_block: i32,
+    // 消えたブロックだけで送れるお邪魔の数 (後の連鎖は含まない)
+    pub bomb_obstacle: i32,
+}
+
+// to_bytesの長さ (列ごとに8byteと死んでいるかの1byte)
+pub const ENCODED_SIZE: usize = W * 8 + 1;
+
+// 列ごとのハッシュ。列の値を列ごとに違う値と混ぜる
+fn column_hash(x: usize, c: u64) -> u64 {
+    if c == 0 { 0 } else { rand::splitmix64(c ^ (x as u64).wrapping_mul(0xD6E8FEB86659FD93)) }
 }
 
 #[derive(Clone)]
 pub struct Board {
     column: [u64; W],
     dead: bool,
+    // 全列のcolumn_hashのxor。列を書き換えるたびに更新する
+    hash: u64,
+}
+
+/**
+ * 下位からmaskの立っているビットだけを詰めて取り出す (pext相当)
+ * BMI2が使えればpext命令、使えなければソフトウェア実装を使う
+ */
+#[inline]
+fn pext(x: u64, mask: u64) -> u64 {
+    #[cfg(all(target_arch = "x86_64", target_feature = "bmi2"))]
+    {
+        unsafe { pext_bmi2(x, mask) }
+    }
+    #[cfg(all(target_arch = "x86_64", not(target_feature = "bmi2")))]
+    {
+        if is_x86_feature_detected!("bmi2") {
+            unsafe { pext_bmi2(x, mask) }
+        } else {
+            pext_soft(x, mask)
+        }
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        pext_soft(x, mask)
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "bmi2")]
+unsafe fn pext_bmi2(x: u64, mask: u64) -> u64 {
+    use std::arch::x86_64::*;
+    _pext_u64(x, mask)
+}
+
+/**
+ * maskの連続する1の区間ごとにまとめて詰める
+ * 盤面で使うmaskはほぼ全ビットが1なので区間数は少ない
+ */
+fn pext_soft(x: u64, mask: u64) -> u64 {
+    let mut res = 0;
+    let mut m = mask;
+    let mut k = 0;
+    while m != 0 {
+        let start = m.trailing_zeros();
+        let run = (m >> start).trailing_ones();
+        let run_mask = if run == 64 { !0 } else { (1 << run) - 1 };
+        res |= (x >> start & run_mask) << k;
+        k += run;
+        m &= !(run_mask << start);
+    }
+    res
 }
 
 fn rotate(pattern: &[[u8; 2]; 2], rot: usize) -> [[u8; 2]; 2] {
     let mut rot = rot;
-    let mut pattern = pattern.clone();
+    let mut pattern = *pattern;
     while rot > 0 {
         pattern = [[pattern[1][0], pattern[0][0]],
                     [pattern[1][1], pattern[0][1]]];
@@ -36,6 +124,7 @@ impl Board {
         Self {
             column: [0; W],
             dead: false,
+            hash: 0,
         }
     }
 
@@ -43,21 +132,49 @@ impl Board {
         self.column.iter().all(|b| *b == 0)
     }
 
-    pub fn from_board(board: [u8; (W * H) as usize]) -> Self {
+    pub fn from_board(board: [u8; W * H]) -> Self {
         let mut b = Board::new();
+        for x in 0..W {
+            let c = (0..H).fold(0, |c, y| c | (board[(H-1-y)*W+x] as u64) << (4 * y));
+            b.set_column(x, c);
+        }
+        // eprintln!("{:?}", b);
+        b
+    }
+
+    // from_boardの逆 (上の行から順に並べる)
+    pub fn to_board(&self) -> [u8; W * H] {
+        let mut board = [0; W * H];
         for y in 0..H {
             for x in 0..W {
-                b.column[x] |= (board[(H-1-y)*W+x] as u64) << (4 * y);
+                board[(H-1-y)*W+x] = (self.column[x] >> (4 * y) & 0x0F) as u8;
             }
         }
-        // eprintln!("{:?}", b);
-        b
+        board
     }
 
+    // Debugの盤面部分を/でつないだ1行の形式
+    pub fn to_compact(&self) -> String {
+        self.to_board().chunks(W).map(|row| {
+            row.iter().map(|c| Self::cell_char(*c as u64)).collect::<String>()
+        }).collect::<Vec<_>>().join("/")
+    }
+
+    fn cell_char(c: u64) -> char {
+        if c > VANISH { 'X' } else { std::char::from_digit(c as u32, 10).unwrap() }
+    }
+
+    fn set_column(&mut self, x: usize, c: u64) {
+        self.hash ^= column_hash(x, self.column[x]) ^ column_hash(x, c);
+        self.column[x] = c;
+    }
+
+    #[allow(clippy::manual_div_ceil)]
     fn height(&self, x: usize) -> usize {
         ((64 - self.column[x].leading_zeros() + 3) / 4) as usize
     }
 
+    #[allow(clippy::manual_div_ceil)]
     fn height_by_val(v: u64) -> u8 {
         ((64 - v.leading_zeros() + 3) / 4) as u8
     }
@@ -65,7 +182,7 @@ impl Board {
     fn fall(&mut self, x: usize, v: u64) {
         let h = self.height(x);
         if h == 16 { self.dead = true; return; }
-        self.column[x] ^= v << (h * 4);
+        self.set_column(x, self.column[x] ^ v << (h * 4));
     }
 
     pub fn put_one(&mut self, v: u64, pos: usize) -> action::ActionResult {
@@ -84,7 +201,7 @@ impl Board {
         });
         // let num_obstacle_row = Self::calc_obstacle_mask(self.column[0]).count_ones() / 4;
 
-        let vanish_result = (0..W).map(|x| {
+        let vanish_result = (0..W).filter_map(|x| {
             let l = {
                 let mut l = H;
                 if x > 0 { l = std::cmp::min(l, highest_obstacle_row[x-1]); }
@@ -93,22 +210,18 @@ impl Board {
             };
             let h = std::cmp::max(heights[x], 1) - 1;
 
-            let r = (l..h).map(|y| {
+            (l..h).map(|y| {
                 if (self.column[x] >> (y*4) & 0x0F) == OBSTACLE {
                     return Default::default();
                 }
 
                 let mut b = self.clone();
-                unsafe {
-                    use std::arch::x86_64::*;
-                    b.column[x] = _pext_u64(b.column[x], !(0x0F << (y*4)));
-                }
+                b.set_column(x, pext(b.column[x], !(0x0F << (y*4))));
                 let changed = 1<<x;
                 let r = b.vanish(changed);
                 (b, r, (x, y))
-            }).max_by_key(|r| (r.1).0);
-            r
-        }).filter(|r| r.is_some()).map(|r| r.unwrap()).max_by_key(|r| (r.1).0);
+            }).max_by_key(|r| (r.1).0)
+        }).max_by_key(|r| (r.1).0);
 
         let (board, vanish_result, p) = vanish_result.unwrap_or(Default::default());
         // (board, score_calculator::ScoreCalculator::calc_chain_result(vanish_result.0, vanish_result.1), p)
@@ -131,7 +244,8 @@ impl Board {
         score_calculator::ScoreCalculator::calc_chain_result(vanish_result.0, vanish_result.1)
     }
 
-    pub fn use_skill(&mut self) -> action::ActionResult {
+    // スキルで消えるマスク (お邪魔と空白は除く)
+    fn calc_bomb_mask(&self) -> [u64; W] {
         let mut vanished = [0; W];
 
         (0..W).for_each(|x| {
@@ -142,14 +256,18 @@ impl Board {
             if x < W - 1 { vanished[x+1] |= bombed_mask; }
         });
 
-        let mut bombed_block = 0;
         (0..W).for_each(|x| {
             let obstacle_mask = Self::calc_obstacle_mask(self.column[x]);
             let empty_mask = Self::calc_empty_mask(self.column[x]);
             vanished[x] &= !obstacle_mask;
             vanished[x] &= !empty_mask;
-            bombed_block += vanished[x].count_ones() / 4;   // 4bit maskなので4で割る
         });
+        vanished
+    }
+
+    pub fn use_skill(&mut self) -> action::ActionResult {
+        let vanished = self.calc_bomb_mask();
+        let bombed_block = vanished.iter().map(|v| v.count_ones() / 4).sum::<u32>();   // 4bit maskなので4で割る
 
         let changed = self.fall_by_mask(&vanished);
         let vanish_result = self.vanish(changed);
@@ -157,6 +275,11 @@ impl Board {
     }
 
     pub fn calc_feature(&self) -> Feature {
+        self.calc_feature_with_potential(&self.calc_max_rensa_by_erase_block().1)
+    }
+
+    // calc_max_rensa_by_erase_blockの結果を既に持っているときはこちらを使う
+    pub fn calc_feature_with_potential(&self, potential: &action::ActionResult) -> Feature {
         let mut keima = 0;
         let mut keima2 = 0;
         let mut tate = 0;
@@ -190,12 +313,55 @@ impl Board {
         
         let num_block = (0..W).map(|x| self.height(x) as i32).sum();
 
+        let mut yoko = 0;
+        let mut naname = 0;
+        for i in 0..W-2 {
+            yoko += Self::calc_remove(self.column[i], self.column[i+2]).count_ones() / 4;
+            naname += Self::calc_remove(self.column[i], self.column[i+2]<<8).count_ones() / 4;
+            naname += Self::calc_remove(self.column[i], self.column[i+2]>>8).count_ones() / 4;
+        }
+
+        let sum = heights.iter().sum::<usize>() as i32;
+        let sum2 = heights.iter().map(|h| (h * h) as i32).sum::<i32>();
+        let bumpiness = heights.windows(2).map(|h| (h[0] as i32 - h[1] as i32).abs()).sum();
+
+        let mut fives = 0;
+        let mut obstacle_rows = [0; W];
+        let mut buried = 0;
+        for (c, rows) in self.column.iter().zip(obstacle_rows.iter_mut()) {
+            fives += Self::calc_five_mask(*c).count_ones() / 4;
+            let obstacle_mask = Self::calc_obstacle_mask(*c);
+            *rows = (obstacle_mask.count_ones() / 4) as i32;
+            // 一番上のお邪魔より下は空白がない
+            let below = if obstacle_mask == 0 { 0 } else { u64::MAX >> obstacle_mask.leading_zeros() };
+            buried += (below & !obstacle_mask).count_ones() / 4;
+        }
+
         Feature {
             keima: keima as i32,
             keima2: keima2 as i32,
             tate: tate as i32,
             tate2: tate2 as i32,
             num_block,
+            yoko: yoko as i32,
+            naname: naname as i32,
+            height_var: W as i32 * sum2 - sum * sum,
+            bumpiness,
+            fives: fives as i32,
+            obstacle_rows,
+            buried: buried as i32,
+            potential_chains: potential.chains as i32,
+        }
+    }
+
+    // 連鎖は起こさずにスキルの威力だけを見る
+    pub fn calc_bomb_feature(&self) -> BombFeature {
+        let fives = self.column.iter().map(|c| Self::calc_five_mask(*c).count_ones() / 4).sum::<u32>();
+        let bombed_block = self.calc_bomb_mask().iter().map(|v| v.count_ones() / 4).sum::<u32>();
+        BombFeature {
+            fives: fives as i32,
+            bombed_block: bombed_block as i32,
+            bomb_obstacle: score_calculator::ScoreCalculator::calc_bomb_result(bombed_block as u8, 0, 0).obstacle,
         }
     }
 
@@ -243,18 +409,17 @@ impl Board {
 
     fn fall_by_mask(&mut self, mask: &[u64]) -> usize {
         let mut changed = 0;
-        for i in 0..mask.len() {
-            if mask[i] != 0 {
+        for (i, m) in mask.iter().enumerate() {
+            if *m != 0 {
                 changed |= 1 << i;
-            }
-            unsafe {
-                use std::arch::x86_64::*;
-                self.column[i] = _pext_u64(self.column[i], !mask[i]);
+                self.set_column(i, pext(self.column[i], !m));
             }
         }
         changed
     }
 
+    // i+0は隣の列との対応を揃えて見せるため
+    #[allow(clippy::identity_op)]
     fn vanish(&mut self, changed: usize) -> (u8, i8) {
         let mut rensa = 0;
         let mut changed = changed;
@@ -344,9 +509,44 @@ impl Board {
     }
 
     pub fn hash(&self) -> u64 {
-        let mut h = 0;
-        self.column.iter().for_each(|c| h = h*31+c);
-        h
+        self.hash
+    }
+
+    fn calc_hash(&self) -> u64 {
+        self.column.iter().enumerate().fold(0, |h, (x, c)| h ^ column_hash(x, *c))
+    }
+
+    // 各列をリトルエンディアンで並べ、最後に死んでいるかを付ける
+    pub fn to_bytes(&self) -> [u8; ENCODED_SIZE] {
+        let mut bytes = [0; ENCODED_SIZE];
+        self.column.iter().enumerate().for_each(|(x, c)| bytes[x * 8..x * 8 + 8].copy_from_slice(&c.to_le_bytes()));
+        bytes[W * 8] = self.dead as u8;
+        bytes
+    }
+
+    // to_bytesの逆。ありえない盤面ならNone
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != ENCODED_SIZE || bytes[W * 8] > 1 {
+            return None;
+        }
+        let mut b = Board::new();
+        for x in 0..W {
+            let mut c = [0; 8];
+            c.copy_from_slice(&bytes[x * 8..x * 8 + 8]);
+            let c = u64::from_le_bytes(c);
+            // 数字かお邪魔が下から隙間なく積まれている
+            let h = Self::height_by_val(c) as u64;
+            let valid = (0..h).all(|y| {
+                let v = c >> (y * 4) & 0x0F;
+                (1..VANISH).contains(&v) || v == OBSTACLE
+            });
+            if !valid {
+                return None;
+            }
+            b.set_column(x, c);
+        }
+        b.dead = bytes[W * 8] == 1;
+        Some(b)
     }
 }
 
@@ -357,17 +557,71 @@ impl std::fmt::Debug for Board {
         for y in (0..H).rev() {
             // let mut res = String::new();
             self.column.iter().for_each(|c| {
-                let c = c >> (y * 4) & 0xF;
-                let c = if c > VANISH { 'X' } else { std::char::from_digit(c as u32, 10).unwrap() };
+                let c = Self::cell_char(c >> (y * 4) & 0xF);
                 // res += &c.to_string();
                 write!(f, "{}", c).expect("");
             });
-            writeln!(f, "").expect("");
+            writeln!(f).expect("");
         }
         Ok(())
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseBoardError {
+    pub row: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for ParseBoardError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "row {}: {}", self.row, self.message)
+    }
+}
+
+impl std::error::Error for ParseBoardError {}
+
+// Debugの出力 ("dump board"と列の値の行は無くても良い) か、行を/で区切った1行の形式を読む
+// 上の行から順に数字とX (お邪魔) を並べる。.は0と同じ。行が足りなければ上は空とみなす
+impl std::str::FromStr for Board {
+    type Err = ParseBoardError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = |row, message: String| Err(ParseBoardError { row, message });
+        let rows = s.lines().flat_map(|l| l.split('/')).map(|r| r.trim())
+            .filter(|r| !r.is_empty() && *r != "dump board" && !r.starts_with('['))
+            .collect::<Vec<_>>();
+        if rows.len() > H {
+            return error(H + 1, format!("more than {} rows", H));
+        }
+
+        let mut board = [0; W * H];
+        let offset = H - rows.len();
+        for (i, row) in rows.iter().enumerate() {
+            if row.chars().count() != W {
+                return error(i + 1, format!("expected {} cells, found {:?}", W, row));
+            }
+            for (x, c) in row.chars().enumerate() {
+                board[(offset + i) * W + x] = match c {
+                    '.' => 0,
+                    'X' => OBSTACLE as u8,
+                    c if c.is_ascii_digit() => c as u8 - b'0',
+                    c => return error(i + 1, format!("invalid cell {:?}", c)),
+                };
+            }
+        }
+
+        // 下が空いているブロックは実際の盤面には無い
+        for x in 0..W {
+            let column = (0..H).map(|y| board[(H-1-y)*W+x]).collect::<Vec<_>>();
+            if let Some(y) = (1..H).find(|y| column[*y] != 0 && column[*y - 1] == 0) {
+                return error(H - y - offset, format!("floating block in column {}", x));
+            }
+        }
+        Ok(Self::from_board(board))
+    }
+}
+
 impl Default for Board {
     fn default() -> Self {
         Self::new()
@@ -384,18 +638,192 @@ impl Eq for Board {}
 
 #[test]
 fn board_test_1() {
-    let mut board = Board::new();
-    board.column[0] = 0x07B1819;
-    board.column[1] = 0x0008832;
+    let mut board: Board = "
+        7000000000
+        X000000000
+        1800000000
+        8800000000
+        1300000000
+        9200000000
+    ".parse().unwrap();
     board.put(&[[1,9],[0,0]], 0, 0);
-    assert_eq!(board.column, [27, 136, 0, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(board, "1800000000/X800000000".parse().unwrap());
 }
 
 #[test]
 fn board_test_2() {
-    let mut board = Board::new();
-    board.column[0] = 0x17B1819;
-    board.column[1] = 0x0098832;
+    let mut board: Board = "
+        1000000000
+        7000000000
+        X900000000
+        1800000000
+        8800000000
+        1300000000
+        9200000000
+    ".parse().unwrap();
     board.put(&[[9,5],[0,3]], 1, 3);
-    assert_eq!(board.column, [11, 1416, 3, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(board, "0500000000/0800000000/X830000000".parse().unwrap());
+}
+
+#[test]
+fn board_test_from_str() {
+    let mut board = Board::new();
+    board.set_column(0, 0x07B1819);
+    board.set_column(9, 0x0008832);
+    assert_eq!(format!("{:?}", board).parse::<Board>().unwrap(), board);
+    assert_eq!(board.to_compact().parse::<Board>().unwrap(), board);
+    assert_eq!(board.to_compact().split('/').count(), H);
+    assert_eq!("X.3.......\n1.2......5".parse::<Board>().unwrap().column, [0xB1, 0, 0x32, 0, 0, 0, 0, 0, 0, 5]);
+    assert_eq!("".parse::<Board>().unwrap(), Board::new());
+
+    assert_eq!("100000000".parse::<Board>().unwrap_err().row, 1);
+    assert_eq!("0000000000/100000000a".parse::<Board>().unwrap_err().row, 2);
+    assert_eq!("1000000000/0000000000".parse::<Board>().unwrap_err().message, "floating block in column 0");
+    assert!(vec!["0000000000"; H + 1].join("/").parse::<Board>().is_err());
+}
+
+#[test]
+fn board_test_to_board() {
+    let mut board = Board::new();
+    board.set_column(0, 0x07B1819);
+    board.set_column(9, 0x0008832);
+    assert_eq!(Board::from_board(board.to_board()), board);
+    assert_eq!(board.to_board()[(H-1)*W], 9);
+}
+
+#[test]
+fn board_test_hash() {
+    let mut rand = rand::XorShiftL::from_seed(3);
+    let mut board = Board::new();
+    let mut hashes = std::collections::HashSet::new();
+    for _ in 0..200 {
+        let r = rand.next();
+        let pack = [[(r % 9 + 1) as u8, (r >> 8) as u8 % 10], [(r >> 16) as u8 % 10, 0]];
+        board.put(&pack, (r >> 24) as usize % (W - 1), (r >> 32) as usize % 4);
+        if r >> 40 & 7 == 0 {
+            board.use_skill();
+        }
+        if r >> 44 & 15 == 0 {
+            board.fall_obstacle();
+        }
+        if board.is_dead() {
+            board = Board::new();
+        }
+        assert_eq!(board.hash(), board.calc_hash());
+        hashes.insert((board.hash(), board.column));
+    }
+    // 盤面が違えばハッシュも違う
+    let boards = hashes.iter().map(|h| h.1).collect::<std::collections::HashSet<_>>();
+    assert_eq!(hashes.iter().map(|h| h.0).collect::<std::collections::HashSet<_>>().len(), boards.len());
+    assert_eq!(Board::new().hash(), 0);
+}
+
+#[test]
+fn board_test_bytes() {
+    let board: Board = "X7........\n12.5.....X".parse().unwrap();
+    let bytes = board.to_bytes();
+    let decoded = Board::from_bytes(&bytes).unwrap();
+    assert_eq!(decoded, board);
+    assert_eq!(decoded.hash(), board.hash());
+    assert!(!decoded.is_dead());
+
+    let mut dead = bytes;
+    dead[W * 8] = 1;
+    assert!(Board::from_bytes(&dead).unwrap().is_dead());
+    assert!(Board::from_bytes(&bytes[1..]).is_none());
+    let mut floating = bytes;
+    floating[8] = 0x10;
+    assert!(Board::from_bytes(&floating).is_none());
+    let mut vanish = bytes;
+    vanish[0] = 0x0A;
+    assert!(Board::from_bytes(&vanish).is_none());
+}
+
+#[test]
+fn board_test_feature() {
+    let board: Board = "5.9.......\nX.7.......\n12X83.....".parse().unwrap();
+    let feature = board.calc_feature();
+    // 2と8が1列空けて横に、1と9が1列空けて斜めに並ぶ
+    assert_eq!((feature.yoko, feature.naname), (1, 1));
+    // 高さは3,1,3,1,1
+    assert_eq!(feature.height_var, 10 * (9 + 1 + 9 + 1 + 1) - 9 * 9);
+    assert_eq!(feature.bumpiness, 2 + 2 + 2 + 1);
+    assert_eq!(feature.fives, 1);
+    assert_eq!(feature.obstacle_rows, [1, 0, 1, 0, 0, 0, 0, 0, 0, 0]);
+    // お邪魔の下にあるのは左下の1だけ
+    assert_eq!(feature.buried, 1);
+    assert_eq!(feature.potential_chains, board.calc_max_rensa_by_erase_block().1.chains as i32);
+    assert_eq!(Board::new().calc_feature(), Feature::default());
+    let rensa2 = board_test_rensa2();
+    assert_eq!(rensa2.calc_feature().potential_chains, rensa2.calc_max_rensa_by_erase_block().1.chains as i32);
+    assert!(rensa2.calc_feature().potential_chains > 0);
+}
+
+#[test]
+fn board_test_bomb_feature() {
+    let board: Board = "1.........\n52........\nX34.......".parse().unwrap();
+    let feature = board.calc_bomb_feature();
+    // 5の周りのうちお邪魔と空白を除いた4個が消える
+    assert_eq!((feature.fives, feature.bombed_block), (1, 4));
+    assert_eq!(feature.bomb_obstacle, score_calculator::ScoreCalculator::calc_bomb_result(4, 0, 0).obstacle);
+
+    let mut bombed = board.clone();
+    assert_eq!(bombed.use_skill().obstacle, feature.bomb_obstacle);
+    assert_eq!(bombed, "X.4.......".parse().unwrap());
+    assert_eq!(Board::new().calc_bomb_feature().bombed_block, 0);
+}
+
+// 7を2列目に落とすと2連鎖する盤面
+#[cfg(test)]
+pub(crate) fn board_test_rensa2() -> Board {
+    ".6......../.2......../43........".parse().unwrap()
+}
+
+#[cfg(test)]
+fn pext_naive(x: u64, mask: u64) -> u64 {
+    let mut res = 0;
+    let mut k = 0;
+    for i in 0..64 {
+        if mask >> i & 1 == 1 {
+            res |= (x >> i & 1) << k;
+            k += 1;
+        }
+    }
+    res
+}
+
+#[test]
+fn pext_soft_test() {
+    let mut rand = rand::XorShiftL::from_seed(1);
+    assert_eq!(pext_soft(!0, !0), !0);
+    assert_eq!(pext_soft(0x1234, 0), 0);
+    for _ in 0..10000 {
+        let x = rand.next();
+        let mask = rand.next() & rand.next();
+        assert_eq!(pext_soft(x, mask), pext_naive(x, mask));
+        assert_eq!(pext_soft(x, !mask), pext_naive(x, !mask));
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn pext_differential_test() {
+    if !is_x86_feature_detected!("bmi2") {
+        return;
+    }
+    let mut rand = rand::XorShiftL::from_seed(2);
+    for _ in 0..10000 {
+        let x = rand.next();
+        let mask = rand.next();
+        assert_eq!(pext_soft(x, mask), unsafe { pext_bmi2(x, mask) });
+    }
+
+    // 盤面と同じ形(4bit単位で消える)のmaskで列が一致すること
+    for _ in 0..10000 {
+        let h = (rand.next() % 17) as usize;
+        let column = if h == 0 { 0 } else { rand.next() >> (64 - 4 * h) };
+        let r = rand.next();
+        let removed = (0..16).fold(0, |m, y| if r >> y & 1 == 1 { m | 0x0F << (y * 4) } else { m });
+        assert_eq!(pext_soft(column, !removed), unsafe { pext_bmi2(column, !removed) });
+    }
 }
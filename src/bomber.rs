@@ -0,0 +1,128 @@
+use super::action;
+use super::board;
+use super::player;
+use super::rand;
+use super::rensa_plan;
+use super::replay;
+use super::strategy;
+use super::consts::H;
+
+// 1回の探索で組む手数
+const MAX_TURN: usize = 8;
+const THINK_TIME_IN_MILLI: u64 = 3000;
+// 持ち時間がこれより少なければ短く探索する
+const EMERGENCY_TIME_IN_MILLI: usize = 30 * 1000;
+const EMERGENCY_THINK_TIME_IN_MILLI: u64 = 500;
+// これ以上送れるならスキルを使う
+const FIRE_OBSTACLES: i32 = 60;
+// ここまで積んだら小さくてもスキルで崩す
+const DANGER_HEIGHT: usize = H - 3;
+
+// ゲージを溜めつつ、5の周りに消せるブロックを集める評価
+// 探索の枝刈りにはcalc_max_rensa_by_erase_blockの結果も渡ってくるので、連鎖のお邪魔数はほとんど見ない
+pub fn evaluate(result: &action::ActionResult, player: &player::Player, feature: &board::Feature) -> i64 {
+    let bomb = player.board.calc_bomb_feature();
+    let guage = std::cmp::min(player.skill_guage, 80) as i64;
+    let max_height = (std::cmp::max(DANGER_HEIGHT, player.board.max_height()) - DANGER_HEIGHT) as i64;
+    guage * 2000
+        + bomb.bombed_block as i64 * 1000
+        + bomb.fives as i64 * 100
+        + feature.num_block as i64 * 10
+        + std::cmp::min(result.obstacle, 10) as i64
+        - max_height * 50000
+}
+
+// スキルで攻めるAI
+// 小さい連鎖でゲージを溜めながら5の周りにブロックを集め、十分送れるようになったらスキルを使う
+pub struct BomberStrategy {
+    rand: rand::XorShiftL,
+    num_threads: usize,
+    current_best: replay::Replay,
+}
+
+impl BomberStrategy {
+    pub fn new() -> Self {
+        Self {
+            rand: rand::XorShiftL::new(),
+            num_threads: rensa_plan::available_threads(),
+            current_best: replay::Replay::new(),
+        }
+    }
+
+    pub fn set_num_threads(&mut self, num_threads: usize) {
+        self.num_threads = num_threads;
+    }
+
+    fn should_fire(player: &player::Player) -> bool {
+        if !player.can_use_skill() {
+            return false;
+        }
+        let obstacle = player.board.clone().use_skill().obstacle;
+        obstacle >= FIRE_OBSTACLES || (obstacle > 0 && player.board.max_height() >= DANGER_HEIGHT)
+    }
+
+    // 一番深くまで組めた計画を使う
+    fn search(&mut self, s: &strategy::Snapshot) -> replay::Replay {
+        let think_time_in_milli = if s.rest_time_in_milli < EMERGENCY_TIME_IN_MILLI { EMERGENCY_THINK_TIME_IN_MILLI } else { THINK_TIME_IN_MILLI };
+        let context = rensa_plan::PlanContext {
+            plan_start_turn: s.turn,
+            max_turn: std::cmp::min(MAX_TURN, s.packs.len() - s.turn),
+            think_time_in_milli,
+            player: s.player.clone(),
+            packs: s.packs,
+            num_threads: self.num_threads,
+            ..Default::default()
+        };
+        let plans = rensa_plan::calc_rensa_plan(&context, &mut self.rand, evaluate);
+        let best = plans.into_iter().rev().find(|p| !p.is_empty()).unwrap_or_default();
+        eprintln!("bomber think done: {} {} guage={} bomb={}", s.turn, best.len(), s.player.skill_guage, s.player.board.calc_bomb_feature().bombed_block);
+        best
+    }
+}
+
+impl strategy::Strategy for BomberStrategy {
+    fn think(&mut self, s: &strategy::Snapshot) -> Option<strategy::Decision> {
+        if Self::should_fire(s.player) {
+            self.current_best.clear();
+            return Some(strategy::Decision::Action(action::Action::UseSkill));
+        }
+        if !self.current_best.can_replay(s.player, &[]) {
+            self.current_best = self.search(s);
+        }
+        self.current_best.replay().map(strategy::Decision::Action)
+    }
+
+    fn name(&self) -> &str {
+        "test-bomber-ai"
+    }
+}
+
+impl Default for BomberStrategy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn bomber_test_think() {
+    use strategy::Strategy;
+    let packs = vec![[[5, 1], [2, 5]]; super::consts::MAX_TURN];
+    let enemy = player::Player::default();
+    // 5の周りは全部1なので消えない
+    let board: board::Board = ["5151515151", "1111111111"].repeat(3).join("/").parse().unwrap();
+    let mut bomber = BomberStrategy::new();
+    bomber.set_num_threads(1);
+    let mut think = |player: &player::Player, rest_time_in_milli| {
+        let s = strategy::Snapshot { turn: 0, rest_time_in_milli, player, enemy: &enemy, packs: &packs };
+        match bomber.think(&s) {
+            Some(strategy::Decision::Action(a)) => a,
+            _ => unreachable!(),
+        }
+    };
+
+    // ゲージが溜まっていて十分送れるならスキルを使う
+    assert_eq!(think(&player::Player::new(board.clone(), 0, 80), 0), action::Action::UseSkill);
+    // ゲージが足りなければ組む
+    assert_ne!(think(&player::Player::new(board, 0, 70), 0), action::Action::UseSkill);
+    assert_ne!(think(&player::Player::default(), 0), action::Action::UseSkill);
+}
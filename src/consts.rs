@@ -6,3 +6,4 @@ pub const H: usize = 16;
 pub const VANISH: u64 = 10;
 pub const OBSTACLE: u64 = VANISH + 1;
 pub const MAX_TURN: usize = 500;
+pub const MAX_SKILL_GUAGE: i32 = 100;
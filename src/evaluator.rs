@@ -0,0 +1,198 @@
+use std::path::Path;
+
+use super::action;
+use super::board;
+use super::player;
+use super::consts::H;
+
+// 探索で1手ごとに局面を評価する関数
+// 同じ型の関数やクロージャはそのまま使える
+pub trait Evaluator: Sync + Send {
+    fn evaluate(&self, result: &action::ActionResult, player: &player::Player, feature: &board::Feature) -> i64;
+}
+
+impl<F> Evaluator for F
+    where F: Fn(&action::ActionResult, &player::Player, &board::Feature) -> i64 + Sync + Send
+{
+    fn evaluate(&self, result: &action::ActionResult, player: &player::Player, feature: &board::Feature) -> i64 {
+        self(result, player, feature)
+    }
+}
+
+// LinearEvaluatorの項 (設定ファイルのキー)
+pub const TERMS: [&str; 16] = [
+    // 送るお邪魔の数 (obstacle_capまで)
+    "obstacle",
+    "fire_height",
+    // H-2を超えて積んだ高さ
+    "over_height",
+    "keima",
+    "keima2",
+    "tate",
+    "tate2",
+    "num_block",
+    "yoko",
+    "naname",
+    "height_var",
+    "bumpiness",
+    "fives",
+    // 全部の列のお邪魔の段数の和
+    "obstacle_rows",
+    "buried",
+    "potential_chains",
+];
+
+const DEFAULT_WEIGHTS: [i64; TERMS.len()] = [1000000, 1000, -10000, 50, 1, 40, 1, 2000, 0, 0, 0, 0, 0, 0, 0, 0];
+const DEFAULT_OBSTACLE_CAP: i32 = 200;
+
+fn terms(result: &action::ActionResult, player: &player::Player, feature: &board::Feature, obstacle_cap: i32) -> [i64; TERMS.len()] {
+    let over_height = std::cmp::max(H - 2, player.board.max_height()) - (H - 2);
+    [
+        std::cmp::min(result.obstacle, obstacle_cap) as i64,
+        result.fire_height as i64,
+        over_height as i64,
+        feature.keima as i64,
+        feature.keima2 as i64,
+        feature.tate as i64,
+        feature.tate2 as i64,
+        feature.num_block as i64,
+        feature.yoko as i64,
+        feature.naname as i64,
+        feature.height_var as i64,
+        feature.bumpiness as i64,
+        feature.fives as i64,
+        feature.obstacle_rows.iter().sum::<i32>() as i64,
+        feature.buried as i64,
+        feature.potential_chains as i64,
+    ]
+}
+
+#[derive(Debug)]
+pub struct ConfigError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(e: std::io::Error) -> Self {
+        ConfigError { line: 0, message: e.to_string() }
+    }
+}
+
+// 項ごとの重みの和で評価する
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LinearEvaluator {
+    weights: [i64; TERMS.len()],
+    // 送るお邪魔はこれ以上数えない
+    pub obstacle_cap: i32,
+}
+
+impl LinearEvaluator {
+    pub fn get(&self, key: &str) -> Option<i64> {
+        match key {
+            "obstacle_cap" => Some(self.obstacle_cap as i64),
+            _ => TERMS.iter().position(|t| *t == key).map(|i| self.weights[i]),
+        }
+    }
+
+    // 知らないキーならfalse
+    pub fn set(&mut self, key: &str, value: i64) -> bool {
+        match key {
+            "obstacle_cap" => self.obstacle_cap = value as i32,
+            _ => match TERMS.iter().position(|t| *t == key) {
+                Some(i) => self.weights[i] = value,
+                None => return false,
+            },
+        }
+        true
+    }
+
+    // 設定ファイルは "key = value" の行と "[site]" の見出しからなる。#から行末まではコメント
+    // 見出しより前の行はどの呼び出し元にも効き、見出しの後の行はsiteが一致するときだけ上書きする
+    pub fn from_config(text: &str, site: &str) -> Result<Self, ConfigError> {
+        let mut evaluator = Self::default();
+        let mut overrides = Vec::new();
+        let mut section: Option<&str> = None;
+        for (n, line) in text.lines().enumerate() {
+            let n = n + 1;
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = Some(name.trim());
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| ConfigError { line: n, message: format!("expected key = value: {:?}", line) })?;
+            let (key, value) = (key.trim(), value.trim());
+            let value = value.parse::<i64>().map_err(|_| ConfigError { line: n, message: format!("invalid value for {}: {:?}", key, value) })?;
+            if evaluator.get(key).is_none() {
+                return Err(ConfigError { line: n, message: format!("unknown key: {}", key) });
+            }
+            match section {
+                None => { evaluator.set(key, value); },
+                Some(s) if s == site => overrides.push((key, value)),
+                Some(_) => {},
+            }
+        }
+        overrides.into_iter().for_each(|(key, value)| { evaluator.set(key, value); });
+        Ok(evaluator)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P, site: &str) -> Result<Self, ConfigError> {
+        Self::from_config(&std::fs::read_to_string(path)?, site)
+    }
+}
+
+impl Default for LinearEvaluator {
+    fn default() -> Self {
+        Self { weights: DEFAULT_WEIGHTS, obstacle_cap: DEFAULT_OBSTACLE_CAP }
+    }
+}
+
+impl Evaluator for LinearEvaluator {
+    fn evaluate(&self, result: &action::ActionResult, player: &player::Player, feature: &board::Feature) -> i64 {
+        terms(result, player, feature, self.obstacle_cap).iter().zip(self.weights.iter()).map(|(t, w)| t * w).sum()
+    }
+}
+
+#[test]
+fn evaluator_test_default() {
+    let player = player::Player::default();
+    let feature = board::Feature { keima: 3, keima2: 4, tate: 5, tate2: 6, num_block: 7, yoko: 8, buried: 9, ..Default::default() };
+    let result = action::ActionResult { obstacle: 300, fire_height: 2, ..Default::default() };
+    let evaluator = LinearEvaluator::default();
+    assert_eq!(evaluator.evaluate(&result, &player, &feature), 200 * 1000000 + 2 * 1000 + 3 * 50 + 4 + 5 * 40 + 6 + 7 * 2000);
+    let mut weighted = evaluator.clone();
+    assert!(weighted.set("buried", -100));
+    assert_eq!(weighted.evaluate(&result, &player, &feature) - evaluator.evaluate(&result, &player, &feature), -900);
+
+    // クロージャも評価関数として渡せる
+    let closure = |result: &action::ActionResult, _: &player::Player, _: &board::Feature| result.obstacle as i64;
+    let evaluators: [&dyn Evaluator; 2] = [&evaluator, &closure];
+    assert_eq!(evaluators[1].evaluate(&result, &player, &feature), 300);
+}
+
+#[test]
+fn evaluator_test_config() {
+    let text = "# 共通\nkeima = 70\nobstacle_cap = 100\n\n[do_counter]\nkeima = 10  # 上書き\n[kill_bommer]\nnum_block=0\n";
+    let rensa = LinearEvaluator::from_config(text, "rensa").unwrap();
+    assert_eq!((rensa.get("keima"), rensa.get("num_block"), rensa.obstacle_cap), (Some(70), Some(2000), 100));
+    let counter = LinearEvaluator::from_config(text, "do_counter").unwrap();
+    assert_eq!((counter.get("keima"), counter.get("num_block")), (Some(10), Some(2000)));
+    assert_eq!(LinearEvaluator::from_config(text, "kill_bommer").unwrap().get("num_block"), Some(0));
+    assert_eq!(LinearEvaluator::from_config("", "rensa").unwrap(), LinearEvaluator::default());
+
+    let e = LinearEvaluator::from_config("keima = 1\n[rensa]\nfoo = 1\n", "rensa").unwrap_err();
+    assert_eq!((e.line, e.message.as_str()), (3, "unknown key: foo"));
+    assert_eq!(LinearEvaluator::from_config("keima = x", "rensa").unwrap_err().line, 1);
+    assert_eq!(LinearEvaluator::from_config("keima", "rensa").unwrap_err().line, 1);
+}
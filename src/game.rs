@@ -0,0 +1,176 @@
+use super::action;
+use super::player;
+use super::types::RawPack;
+
+use super::consts::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Win(usize),
+    Draw,
+}
+
+// 両プレイヤーとパックを持ち、公式ルールで1ターンずつ進める
+#[derive(Clone)]
+pub struct Game {
+    packs: Vec<RawPack>,
+    players: [player::Player; 2],
+    scores: [i32; 2],
+    turn: usize,
+    outcome: Option<Outcome>,
+}
+
+impl Game {
+    pub fn new(packs: Vec<RawPack>) -> Self {
+        Self::with_players(packs, Default::default())
+    }
+
+    pub fn with_players(packs: Vec<RawPack>, players: [player::Player; 2]) -> Self {
+        assert!(!packs.is_empty());
+        Self {
+            packs,
+            players,
+            scores: [0; 2],
+            turn: 0,
+            outcome: None,
+        }
+    }
+
+    pub fn turn(&self) -> usize {
+        self.turn
+    }
+
+    pub fn packs(&self) -> &[RawPack] {
+        &self.packs
+    }
+
+    pub fn pack(&self) -> &RawPack {
+        &self.packs[self.turn]
+    }
+
+    pub fn player(&self, side: usize) -> &player::Player {
+        &self.players[side]
+    }
+
+    pub fn score(&self, side: usize) -> i32 {
+        self.scores[side]
+    }
+
+    pub fn outcome(&self) -> Option<Outcome> {
+        self.outcome
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.outcome.is_some()
+    }
+
+    pub fn is_legal(player: &player::Player, action: &action::Action) -> bool {
+        match action {
+            action::Action::PutBlock { pos, rot } => *pos < W - 1 && *rot < 4,
+            action::Action::UseSkill => player.can_use_skill(),
+        }
+    }
+
+    // 両者の行動を同時に適用する
+    // 反則した側は負け、お邪魔は相殺してから相手に送り、スキルゲージは相手の連鎖で削られる
+    pub fn step(&mut self, actions: [&action::Action; 2]) -> [action::ActionResult; 2] {
+        assert!(!self.is_over());
+
+        let illegal = [0, 1].map(|i| !Self::is_legal(&self.players[i], actions[i]));
+        if illegal[0] || illegal[1] {
+            self.outcome = Some(Self::judge(illegal));
+            return Default::default();
+        }
+
+        let pack = self.packs[self.turn];
+        let results = [0, 1].map(|i| self.players[i].put(&pack, actions[i]));
+        let send = [0, 1].map(|i| self.players[i].take_send_obstacles());
+        (0..2).for_each(|i| {
+            self.scores[i] += results[i].score;
+            self.players[i].add_obstacles(send[1 - i]);
+            self.players[i].decrease_skill_guage(results[1 - i].skill_guage);
+        });
+        self.turn += 1;
+
+        let dead = [0, 1].map(|i| self.players[i].board.is_dead());
+        if dead[0] || dead[1] {
+            self.outcome = Some(Self::judge(dead));
+        } else if self.turn == self.packs.len() {
+            self.outcome = Some(Outcome::Draw);
+        }
+        results
+    }
+
+    // 時間切れや不正な出力など盤外の理由で負けにする
+    pub fn forfeit(&mut self, lost: [bool; 2]) {
+        assert!(lost[0] || lost[1]);
+        self.outcome = Some(Self::judge(lost));
+    }
+
+    fn judge(lost: [bool; 2]) -> Outcome {
+        match lost {
+            [true, false] => Outcome::Win(1),
+            [false, true] => Outcome::Win(0),
+            _ => Outcome::Draw,
+        }
+    }
+}
+
+#[cfg(test)]
+fn game_test_players(obstacle: i32) -> [player::Player; 2] {
+    [player::Player::new(super::board::board_test_rensa2(), obstacle, 0), player::Player::new(Default::default(), 2, 50)]
+}
+
+#[test]
+fn game_test_obstacle_exchange() {
+    let packs = vec![[[7, 0], [0, 0]], [[1, 1], [0, 0]]];
+    let put = action::Action::PutBlock { pos: 2, rot: 0 };
+
+    let mut game = Game::with_players(packs.clone(), game_test_players(0));
+    let results = game.step([&put, &put]);
+    assert_eq!(results[0].chains, 2);
+    assert_eq!(results[0].obstacle, 1);
+    assert_eq!(results[1].chains, 0);
+    assert_eq!(game.player(0).obstacle, 0);
+    assert_eq!(game.player(1).obstacle, 3);
+    assert_eq!(game.player(0).skill_guage, 8);
+    assert_eq!(game.player(1).skill_guage, 50);
+    assert_eq!(game.score(0), 2);
+    assert!(!game.is_over());
+
+    game.step([&put, &put]);
+    assert_eq!(game.outcome(), Some(Outcome::Draw));
+
+    // 自分のお邪魔と相殺されるので相手には届かない
+    let mut game = Game::with_players(packs, game_test_players(3));
+    game.step([&put, &put]);
+    assert_eq!(game.player(0).obstacle, 2);
+    assert_eq!(game.player(1).obstacle, 2);
+}
+
+#[test]
+fn game_test_illegal_skill() {
+    let mut game = Game::new(vec![[[1, 9], [0, 0]]; 4]);
+    let put = action::Action::PutBlock { pos: 0, rot: 0 };
+    game.step([&put, &action::Action::UseSkill]);
+    assert_eq!(game.outcome(), Some(Outcome::Win(0)));
+}
+
+#[test]
+fn game_test_dead() {
+    let left = action::Action::PutBlock { pos: 0, rot: 0 };
+
+    let mut game = Game::new(vec![[[1, 1], [1, 1]]; 20]);
+    while !game.is_over() {
+        game.step([&left, &left]);
+    }
+    assert_eq!(game.outcome(), Some(Outcome::Draw));
+    assert_eq!(game.turn(), 9);
+
+    let mut game = Game::new(vec![[[1, 1], [1, 1]]; 20]);
+    while !game.is_over() {
+        let spread = action::Action::PutBlock { pos: game.turn() % 5 * 2, rot: 0 };
+        game.step([&left, &spread]);
+    }
+    assert_eq!(game.outcome(), Some(Outcome::Win(1)));
+}
@@ -7,11 +7,22 @@
 pub mod action;
 pub mod board;
 pub mod rensa_plan;
+pub mod transposition;
+pub mod node_pool;
 pub mod rensa_ai;
 pub mod best_ai;
+pub mod opponent;
+pub mod bomber;
+pub mod evaluator;
+pub mod strategy;
 pub mod rand;
 pub mod score_calculator;
 pub mod player;
 pub mod types;
 pub mod consts;
 pub mod replay;
+pub mod plan;
+pub mod game;
+pub mod protocol;
+pub mod pack;
+pub mod record;
@@ -0,0 +1,119 @@
+use std::sync::Mutex;
+
+// NodePoolの中の1要素の場所
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    shard: u32,
+    index: u32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    pub allocs: usize,
+    // allocのうち空きスロットを使い回した数
+    pub reused: usize,
+    pub frees: usize,
+    // シャードごとの同時に使った最大数の和
+    pub peak: usize,
+    pub slots: usize,
+    // 事前に確保した分を超えて伸ばした回数
+    pub grows: usize,
+}
+
+impl PoolStats {
+    fn add(&mut self, other: &PoolStats) {
+        self.allocs += other.allocs;
+        self.reused += other.reused;
+        self.frees += other.frees;
+        self.peak += other.peak;
+        self.slots += other.slots;
+        self.grows += other.grows;
+    }
+}
+
+struct Shard<T> {
+    slots: Vec<T>,
+    free: Vec<u32>,
+    live: usize,
+    stats: PoolStats,
+}
+
+// 探索の状態を置いておく場所
+// スレッドごとにシャードを分けて確保し、解放したスロットは次の確保で使い回す
+pub struct NodePool<T> {
+    shards: Vec<Mutex<Shard<T>>>,
+}
+
+impl<T: Default> NodePool<T> {
+    pub fn new(shards: usize, capacity_per_shard: usize) -> Self {
+        let shards = (0..shards.max(1)).map(|_| Mutex::new(Shard {
+            slots: Vec::with_capacity(capacity_per_shard),
+            free: Vec::new(),
+            live: 0,
+            stats: Default::default(),
+        })).collect();
+        Self { shards }
+    }
+
+    // 確保は自分のシャードから行う (shardはスレッドの番号)
+    pub fn alloc(&self, shard: usize, value: T) -> NodeId {
+        let shard = shard % self.shards.len();
+        let mut s = self.shards[shard].lock().unwrap();
+        s.stats.allocs += 1;
+        s.live += 1;
+        s.stats.peak = s.stats.peak.max(s.live);
+        let index = match s.free.pop() {
+            Some(index) => {
+                s.stats.reused += 1;
+                s.slots[index as usize] = value;
+                index
+            },
+            None => {
+                if s.slots.len() == s.slots.capacity() {
+                    s.stats.grows += 1;
+                }
+                s.slots.push(value);
+                s.stats.slots += 1;
+                (s.slots.len() - 1) as u32
+            },
+        };
+        NodeId { shard: shard as u32, index }
+    }
+
+    // 取り出して解放する
+    pub fn take(&self, id: NodeId) -> T {
+        let mut s = self.shards[id.shard as usize].lock().unwrap();
+        s.stats.frees += 1;
+        s.live -= 1;
+        s.free.push(id.index);
+        std::mem::take(&mut s.slots[id.index as usize])
+    }
+
+    pub fn free(&self, id: NodeId) {
+        self.take(id);
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let mut stats = PoolStats::default();
+        self.shards.iter().for_each(|s| stats.add(&s.lock().unwrap().stats));
+        stats
+    }
+}
+
+#[test]
+fn node_pool_test_reuse() {
+    let pool = NodePool::new(2, 2);
+    let a = pool.alloc(0, 10);
+    let b = pool.alloc(0, 20);
+    let c = pool.alloc(3, 30);
+    assert_eq!(pool.take(b), 20);
+    let d = pool.alloc(0, 40);
+    assert_eq!(d, b);
+    pool.alloc(0, 50);
+    assert_eq!(pool.take(c), 30);
+    assert_eq!(pool.take(a), 10);
+    assert_eq!(pool.take(d), 40);
+
+    let stats = pool.stats();
+    assert_eq!(stats, PoolStats { allocs: 5, reused: 1, frees: 4, peak: 3 + 1, slots: 4, grows: 1 });
+}
@@ -0,0 +1,279 @@
+use super::action;
+use super::player;
+use super::rand;
+use super::rensa_plan;
+use super::strategy;
+
+// 相手の連鎖を調べる手数
+pub const DEFAULT_HORIZON: usize = 3;
+// 1ターンに相手の探索で使うノード数
+pub const DEFAULT_MAX_NODES: usize = 2000;
+// 相手の探索の置換表はノード数のこの倍だけ用意する
+const TABLE_ENTRIES_PER_NODE: usize = 4;
+
+// この数より少ないターンしか見ていなければ分類しない
+const MIN_OBSERVATIONS: usize = 5;
+// このターンまでにゲージがこれだけ溜まっていたらボマー (kill_bommerと同じ基準)
+const BOMBER_TURN: usize = 10;
+const BOMBER_GUAGE: i32 = 30;
+// このターンより前にこれ以上のお邪魔を送ってきたら速攻
+const EARLY_TURN: usize = 20;
+const EARLY_OBSTACLES: i32 = 10;
+
+// 相手の打ち方
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Playstyle {
+    Unknown,
+    // 大連鎖を組んで撃つ
+    Chainer,
+    // 小さい連鎖でゲージを溜めてスキルで攻める
+    Bomber,
+    // 連鎖が主でたまにスキルも使う
+    SkillUser,
+    // 序盤から小中連鎖で攻める
+    EarlyAttacker,
+}
+
+// 1ターン分の相手の様子
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Observation {
+    pub turn: usize,
+    // potential[i]: i+1手以内に撃てるお邪魔の最大数
+    pub potential: Vec<i32>,
+    pub skill_guage: i32,
+    // 前のターンに相手が打った手とその結果 (盤面から特定できなければNone)
+    pub last_action: Option<(action::Action, action::ActionResult)>,
+}
+
+impl Observation {
+    fn fired(&self) -> i32 {
+        self.last_action.as_ref().map_or(0, |a| a.1.obstacle)
+    }
+
+    fn used_skill(&self) -> bool {
+        matches!(self.last_action, Some((action::Action::UseSkill, _)))
+    }
+}
+
+// 相手が撃ってくると予想するターンとお邪魔の数
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Prediction {
+    pub turn: usize,
+    pub obstacles: i32,
+}
+
+// 毎ターン相手の盤面を短く探索して打ち方と撃つタイミングを予想する
+pub struct OpponentModel {
+    horizon: usize,
+    max_nodes: usize,
+    seed: u64,
+    history: Vec<Observation>,
+    prev: Option<(usize, player::Player)>,
+}
+
+impl OpponentModel {
+    pub fn new(horizon: usize, max_nodes: usize) -> Self {
+        assert!(horizon > 0);
+        Self { horizon, max_nodes, seed: 1, history: Vec::new(), prev: None }
+    }
+
+    // 局面を1ターン分記録する。同じターンを2回渡したら2回目は無視する
+    pub fn observe(&mut self, s: &strategy::Snapshot) -> &Observation {
+        if self.history.last().is_some_and(|o| o.turn == s.turn) {
+            return self.history.last().unwrap();
+        }
+        let last_action = match &self.prev {
+            Some((turn, prev)) if turn + 1 == s.turn => Self::guess_action(prev, &s.packs[*turn], s.enemy),
+            _ => None,
+        };
+        let observation = Observation {
+            turn: s.turn,
+            potential: self.calc_potential(s),
+            skill_guage: s.enemy.skill_guage,
+            last_action,
+        };
+        self.prev = Some((s.turn, s.enemy.clone()));
+        self.history.push(observation);
+        self.history.last().unwrap()
+    }
+
+    pub fn history(&self) -> &[Observation] {
+        &self.history
+    }
+
+    pub fn latest(&self) -> Option<&Observation> {
+        self.history.last()
+    }
+
+    // 前の盤面から今の盤面になる手を探す
+    fn guess_action(prev: &player::Player, pack: &[[u8; 2]; 2], cur: &player::Player) -> Option<(action::Action, action::ActionResult)> {
+        action::Action::all_actions().iter().find_map(|a| {
+            if a == &action::Action::UseSkill && !prev.can_use_skill() {
+                return None;
+            }
+            let mut p = prev.clone();
+            let result = p.put(pack, a);
+            if p.board == cur.board { Some((a.clone(), result)) } else { None }
+        })
+    }
+
+    fn calc_potential(&self, s: &strategy::Snapshot) -> Vec<i32> {
+        let max_turn = std::cmp::min(self.horizon, s.packs.len() - s.turn);
+        let context = rensa_plan::PlanContext {
+            plan_start_turn: s.turn,
+            max_turn,
+            player: s.enemy.clone(),
+            packs: s.packs,
+            num_threads: 1,
+            max_nodes: Some(self.max_nodes),
+            seed: Some(self.seed ^ s.turn as u64),
+            // 毎ターン呼ぶので置換表は探索するノード数に見合った大きさにする
+            table_capacity: self.max_nodes * TABLE_ENTRIES_PER_NODE,
+            ..Default::default()
+        };
+        let plans = rensa_plan::calc_rensa_plan(&context, &mut rand::XorShiftL::from_seed(self.seed), |result, _, feature| {
+            result.obstacle as i64 * 1000 + feature.num_block as i64
+        });
+        let mut max = 0;
+        plans.iter().map(|p| {
+            max = std::cmp::max(max, p.get_obstacle());
+            max
+        }).collect()
+    }
+
+    pub fn playstyle(&self) -> Playstyle {
+        if self.history.len() < MIN_OBSERVATIONS {
+            return Playstyle::Unknown;
+        }
+        let skill_uses = self.history.iter().filter(|o| o.used_skill()).count();
+        let charged = self.history.iter().any(|o| o.turn <= BOMBER_TURN && o.skill_guage >= BOMBER_GUAGE);
+        let early = self.history.iter().any(|o| o.turn < EARLY_TURN && !o.used_skill() && o.fired() >= EARLY_OBSTACLES);
+
+        if charged || skill_uses >= 2 {
+            Playstyle::Bomber
+        } else if skill_uses == 1 {
+            Playstyle::SkillUser
+        } else if early {
+            Playstyle::EarlyAttacker
+        } else {
+            Playstyle::Chainer
+        }
+    }
+
+    // 速攻なら撃てるようになった時点で、それ以外は待っても大きく増えなくなった時点で撃つと見る
+    pub fn predict(&self) -> Option<Prediction> {
+        let o = self.latest()?;
+        let max = *o.potential.iter().max()?;
+        if max <= 0 {
+            return None;
+        }
+        let i = match self.playstyle() {
+            Playstyle::EarlyAttacker => o.potential.iter().position(|p| *p >= EARLY_OBSTACLES),
+            _ => None,
+        }.unwrap_or_else(|| o.potential.iter().position(|p| p * 10 >= max * 8).unwrap());
+        Some(Prediction { turn: o.turn + i, obstacles: o.potential[i] })
+    }
+
+    // calc_rensa_planに渡すお邪魔の想定
+    // 計画は先頭の想定で再生するので撃たれない場合を先頭に置き、予想通りの場合と1手早く撃たれる場合を加える
+    pub fn scenarios(&self) -> Vec<Scenario> {
+        let mut scenarios = vec![Scenario { weight: 1, obstacles: Vec::new() }];
+        let (o, p) = match (self.latest(), self.predict()) {
+            (Some(o), Some(p)) => (o, p),
+            _ => return scenarios,
+        };
+        let at = |weight, i: usize, obstacles: i32| {
+            let mut v = vec![0; i + 1];
+            v[i] = obstacles;
+            Scenario { weight, obstacles: v }
+        };
+        let i = p.turn - o.turn;
+        scenarios.push(at(3, i, p.obstacles));
+        if i > 0 && o.potential[i - 1] > 0 {
+            scenarios.push(at(1, i - 1, o.potential[i - 1]));
+        }
+        scenarios
+    }
+}
+
+// お邪魔の想定 (重み, 各手の後に届く数)。rensa_plan::ObstacleScenarioに変換して使う
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Scenario {
+    pub weight: u32,
+    pub obstacles: Vec<i32>,
+}
+
+impl<'a> From<&'a Scenario> for rensa_plan::ObstacleScenario<'a> {
+    fn from(s: &'a Scenario) -> Self {
+        rensa_plan::ObstacleScenario { weight: s.weight, obstacles: &s.obstacles }
+    }
+}
+
+impl Default for OpponentModel {
+    fn default() -> Self {
+        Self::new(DEFAULT_HORIZON, DEFAULT_MAX_NODES)
+    }
+}
+
+#[cfg(test)]
+fn opponent_test_observation(turn: usize, skill_guage: i32, last_action: Option<action::Action>, fired: i32) -> Observation {
+    let last_action = last_action.map(|a| (a, action::ActionResult { obstacle: fired, ..Default::default() }));
+    Observation { turn, potential: vec![0, 30, 34], skill_guage, last_action }
+}
+
+#[test]
+fn opponent_test_observe() {
+    let packs = vec![[[1, 2], [3, 4]]; super::consts::MAX_TURN];
+    let player = player::Player::default();
+    let enemy = player::Player::default();
+    let mut model = OpponentModel::new(2, 300);
+    let snapshot = |turn, enemy| strategy::Snapshot { turn, rest_time_in_milli: 0, player: &player, enemy, packs: &packs };
+
+    assert_eq!(model.observe(&snapshot(0, &enemy)).last_action, None);
+    let mut moved = enemy.clone();
+    moved.put(&packs[0], &action::Action::PutBlock { pos: 3, rot: 1 });
+    let o = model.observe(&snapshot(1, &moved)).clone();
+    assert_eq!(o.potential.len(), 2);
+    assert!(o.potential[0] <= o.potential[1]);
+    // 同じ盤面になる手なら打った手と違っても良い
+    let mut p = player::Player::default();
+    p.put(&packs[0], &o.last_action.unwrap().0);
+    assert_eq!(p.board, moved.board);
+
+    model.observe(&snapshot(1, &moved));
+    assert_eq!(model.history().len(), 2);
+    assert_eq!(model.playstyle(), Playstyle::Unknown);
+}
+
+#[test]
+fn opponent_test_playstyle() {
+    let put = || Some(action::Action::PutBlock { pos: 0, rot: 0 });
+    let model = |history: Vec<Observation>| OpponentModel { history, ..Default::default() };
+
+    let quiet = (0..6).map(|t| opponent_test_observation(t, 0, put(), 0)).collect::<Vec<_>>();
+    assert_eq!(model(quiet.clone()).playstyle(), Playstyle::Chainer);
+    assert_eq!(model(quiet.clone()).predict(), Some(Prediction { turn: 5 + 1, obstacles: 30 }));
+
+    let mut early = quiet.clone();
+    early[3] = opponent_test_observation(3, 8, put(), 12);
+    assert_eq!(model(early).playstyle(), Playstyle::EarlyAttacker);
+
+    let mut skill = quiet.clone();
+    skill[4] = opponent_test_observation(4, 0, Some(action::Action::UseSkill), 20);
+    assert_eq!(model(skill.clone()).playstyle(), Playstyle::SkillUser);
+    skill[2] = opponent_test_observation(2, 0, Some(action::Action::UseSkill), 20);
+    assert_eq!(model(skill).playstyle(), Playstyle::Bomber);
+
+    let mut charged = quiet;
+    charged[5] = opponent_test_observation(5, 40, put(), 0);
+    let mut charged = model(charged);
+    assert_eq!(charged.playstyle(), Playstyle::Bomber);
+    let scenario = |weight, obstacles: &[i32]| Scenario { weight, obstacles: obstacles.to_vec() };
+    assert_eq!(charged.scenarios(), vec![scenario(1, &[]), scenario(3, &[0, 30])]);
+    charged.history[5].potential = vec![20, 30, 34];
+    let scenarios = charged.scenarios();
+    assert_eq!(scenarios, vec![scenario(1, &[]), scenario(3, &[0, 30]), scenario(1, &[20])]);
+    let plan: rensa_plan::ObstacleScenario = (&scenarios[1]).into();
+    assert_eq!(plan, rensa_plan::ObstacleScenario { weight: 3, obstacles: &[0, 30] });
+    assert_eq!(model(Vec::new()).scenarios(), vec![scenario(1, &[])]);
+}
@@ -0,0 +1,142 @@
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use super::protocol;
+use super::rand;
+use super::types::RawPack;
+
+use super::consts::{MAX_TURN, OBSTACLE};
+
+// 1マスごとの中身の重み
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackDistribution {
+    // cells[v]: マスがvになる重み (0は空き、1から9は数字、OBSTACLEはお邪魔)
+    pub cells: [u32; OBSTACLE as usize + 1],
+    // 1パックに入るブロック数の範囲
+    pub min_blocks: usize,
+    pub max_blocks: usize,
+}
+
+impl PackDistribution {
+    // 数字が大半で、空きが少し混ざる (空き:数字 = 1:7)
+    // 公式サーバーのパックから測った値ではない。公式に近いパックが要るときはfitで実際の試合のパックから作る
+    pub const SYNTHETIC: PackDistribution = PackDistribution { cells: [9, 7, 7, 7, 7, 7, 7, 7, 7, 7, 0, 0], min_blocks: 2, max_blocks: 4, };
+
+    // 実際の試合のパックからマスごとの出現数とブロック数の範囲を数える
+    pub fn fit(packs: &[RawPack]) -> Self {
+        assert!(!packs.is_empty());
+        let mut cells = [0; OBSTACLE as usize + 1];
+        packs.iter().flatten().flatten().for_each(|v| cells[*v as usize] += 1);
+        let blocks = packs.iter().map(|p| p.iter().flatten().filter(|v| **v != 0).count());
+        let min_blocks = blocks.clone().min().unwrap();
+        let max_blocks = blocks.max().unwrap();
+        Self { cells, min_blocks, max_blocks }
+    }
+
+    fn cell(&self, rand: &mut rand::XorShiftL) -> u8 {
+        let total = self.cells.iter().sum::<u32>() as u64;
+        let mut r = rand.gen_range(0..total);
+        self.cells.iter().position(|w| {
+            if r < *w as u64 {
+                return true;
+            }
+            r -= *w as u64;
+            false
+        }).unwrap() as u8
+    }
+
+    pub fn generate_pack(&self, rand: &mut rand::XorShiftL) -> RawPack {
+        assert!(self.cells[1..].iter().any(|w| *w > 0) && self.min_blocks <= self.max_blocks && self.max_blocks <= 4);
+        assert!(self.cells[0] > 0 || self.max_blocks == 4);
+        loop {
+            let mut pack = [[0; 2]; 2];
+            pack.iter_mut().flatten().for_each(|v| *v = self.cell(rand));
+            let blocks = pack.iter().flatten().filter(|v| **v != 0).count();
+            if self.min_blocks <= blocks && blocks <= self.max_blocks {
+                return pack;
+            }
+        }
+    }
+
+    pub fn generate(&self, rand: &mut rand::XorShiftL) -> Vec<RawPack> {
+        (0..MAX_TURN).map(|_| self.generate_pack(rand)).collect()
+    }
+}
+
+// 仮の分布で1試合分のパックを作る (公式のパックと同じ分布にはならない)
+pub fn generate_synthetic(seed: u64) -> Vec<RawPack> {
+    PackDistribution::SYNTHETIC.generate(&mut rand::XorShiftL::from_seed(seed))
+}
+
+// パックファイルは試合開始時の入力と同じ形式
+pub fn read<R: BufRead>(reader: R) -> Result<Vec<RawPack>, protocol::ProtocolError> {
+    Ok(protocol::Parser::new(reader).read_game_input()?.packs)
+}
+
+pub fn write<W: Write>(w: &mut W, packs: &[RawPack]) -> std::io::Result<()> {
+    protocol::GameInput { packs: packs.to_vec() }.write(w)
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<RawPack>, protocol::ProtocolError> {
+    let file = std::fs::File::open(path).map_err(|e| protocol::ProtocolError { line: 0, token: String::new(), kind: protocol::ProtocolErrorKind::Io(e) })?;
+    read(BufReader::new(file))
+}
+
+pub fn save<P: AsRef<Path>>(path: P, packs: &[RawPack]) -> std::io::Result<()> {
+    let mut w = BufWriter::new(std::fs::File::create(path)?);
+    write(&mut w, packs)?;
+    w.flush()
+}
+
+#[test]
+fn pack_test_generate_synthetic() {
+    let packs = generate_synthetic(1);
+    assert_eq!(packs.len(), MAX_TURN);
+    assert_eq!(packs, generate_synthetic(1));
+    assert_ne!(packs, generate_synthetic(2));
+
+    let mut counts = [0; 12];
+    packs.iter().flatten().flatten().for_each(|v| counts[*v as usize] += 1);
+    assert!(counts[1..10].iter().all(|c| *c > 100), "{:?}", counts);
+    assert!(counts[0] > 0 && counts[0] < counts[1..10].iter().sum::<i32>() / 4);
+    assert_eq!(counts[OBSTACLE as usize], 0);
+    assert!(packs.iter().all(|p| p.iter().flatten().filter(|v| **v != 0).count() >= 2));
+
+    let mut cells = [0; OBSTACLE as usize + 1];
+    cells[1] = 1;
+    cells[OBSTACLE as usize] = 1;
+    let dist = PackDistribution { cells, min_blocks: 4, max_blocks: 4 };
+    let packs = dist.generate(&mut rand::XorShiftL::from_seed(3));
+    assert!(packs.iter().flatten().flatten().any(|v| *v as u64 == OBSTACLE));
+    assert!(packs.iter().flatten().flatten().all(|v| *v == 1 || *v as u64 == OBSTACLE));
+}
+
+#[test]
+fn pack_test_fit() {
+    // 数字2つと空き2つのパックだけからなる試合
+    let packs = (0..MAX_TURN).map(|i| [[(i % 9 + 1) as u8, 0], [0, (i % 3 + 7) as u8]]).collect::<Vec<_>>();
+    let dist = PackDistribution::fit(&packs);
+    assert_eq!((dist.min_blocks, dist.max_blocks), (2, 2));
+    assert_eq!(dist.cells[0], MAX_TURN as u32 * 2);
+    assert_eq!(dist.cells[OBSTACLE as usize], 0);
+
+    let generated = dist.generate(&mut rand::XorShiftL::from_seed(5));
+    assert!(generated.iter().all(|p| p.iter().flatten().filter(|v| **v != 0).count() == 2));
+    // 元の試合で7から9が多かった分だけ多く出る
+    let mut counts = [0; 10];
+    generated.iter().flatten().flatten().for_each(|v| counts[*v as usize] += 1);
+    assert!(counts[1..7].iter().all(|c| counts[8] > *c), "{:?}", counts);
+
+    assert_eq!(PackDistribution::fit(&generate_synthetic(6)).max_blocks, 4);
+}
+
+#[test]
+fn pack_test_read_write() {
+    let packs = generate_synthetic(4);
+    let mut buf = Vec::new();
+    write(&mut buf, &packs).unwrap();
+    assert_eq!(read(&buf[..]).unwrap(), packs);
+
+    let e = read(&buf[..buf.len() / 2]).unwrap_err();
+    assert!(e.is_eof());
+}
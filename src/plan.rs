@@ -1,127 +1,145 @@
-
 use super::action;
-use super::board;
 use super::player;
-use super::rand;
-
-pub struct  {
-}
+use super::replay;
 
+// 発火までの手数ごとに連鎖数の大きい計画を上位k個まで持つ
 pub struct Plans {
-    best_plans: Vec<>,
+    k: usize,
+    // plans[i]: i+1手目で発火する計画 (連鎖数の大きい順)
+    plans: Vec<Vec<replay::Replay>>,
 }
 
-impl Plan {
-    pub fn new() -> Self {
-        Self {
-            rand: rand::XorShiftL::new(),
-            packs: Vec::new(),
-            replay: VecDeque::new(),
-        }
+impl Plans {
+    pub fn new(k: usize) -> Self {
+        Self { k, plans: Vec::new(), }
     }
 
-    pub fn set_pack(&mut self, packs: Vec<[[u8; 2]; 2]>) {
-        self.packs = packs;
-    }
-    
-    fn calc_score(&mut self, result: &action::ActionResult, b: &board::Board, search_turn: usize) -> i32 {
-        // let h = b.max_height() as i32;
-        // -(result.obstacle * 10000 - h * 10 + search_turn as i32 * 16 + (self.rand.next() & 0xF) as i32)
-        (result.obstacle * 10000 + search_turn as i32 * 16 + (self.rand.next() & 0xF) as i32)
+    pub fn clear(&mut self) {
+        self.plans.clear();
     }
-    
-    // pub fn calc_rensa_plan(&mut self, cur_turn: usize, max_fire_turn: usize, player: &player::Player, ) {
-    pub fn calc_rensa_plan(&mut self, context: &PlanContext) {
-        let timer = Instant::now();
-
-        // let max_fire_turn = if cur_turn == 0 { 13 } else { 10 };
-        let actions = action::Action::all_actions();
-        // let allow_dead_line = Self::is_dangerous(&player.board);
-
-        let mut heaps = vec![BinaryHeap::new(); context.max_turn];
-
-        let initial_state = BeamState::new(player.clone(), 0, Vec::new());
-        heaps[0].push(initial_state.clone());
-        let mut best = initial_state;
-        loop {
-            let elapsed = timer.elapsed();
-            if elapsed.as_secs() >= 15 {
-                break;
-            }
 
-            (0..max_fire_turn).for_each(|search_turn| {
-                let turn = cur_turn + search_turn;
-                let pack = self.packs[turn].clone();
-                // eprintln!("come: {} {}", search_turn, heaps[search_turn].len());
-                if let Some(b) = heaps[search_turn].pop() {
-                    actions.iter().for_each(|a| {
-                        if &action::Action::UseSkill == a && !b.player.can_use_skill() {
-                            return;
-                        }
-
-                        let mut player = b.player.clone();
-                        let result = player.put(&pack, a);
-                        if player.board.is_dead() {
-                            return;
-                        }
-
-                        let mut actions = b.actions.clone();
-                        actions.push(a.into());
-
-                        let score = self.calc_score(&result, &player.board, search_turn);
-                        if best.score < score {
-                            best = BeamState::new(player.clone(), score, actions.clone());
-                        }
-
-                        if result.chains >= 3 {
-                            return;
-                        }
-
-                        if cur_turn == 0 && player.board.max_height() >= H - 3 {
-                            return;
-                        }
-
-                        if search_turn + 1 < max_fire_turn {
-                            let max_score = (0..W).map(|x| (1..=9).map(|v| {
-                                let mut rensa_eval_board = player.clone();
-                                // let result = rensa_eval_board.put(&fall, &fire_action);
-                                let result = rensa_eval_board.put_one(v, x as usize);
-                                self.calc_score(&result, &player.board, search_turn)
-                            }).max().unwrap()).max().unwrap();
-                            heaps[search_turn + 1].push(BeamState::new(player.clone(), max_score, actions.clone()));
-                        }
-                    });
-                };
-            });
-        }
-        let elapsed = timer.elapsed();
-        let elapsed = format!("{}.{:03}", elapsed.as_secs(), elapsed.subsec_nanos() / 1_000_000);
+    pub fn len(&self) -> usize {
+        self.plans.iter().map(|p| p.len()).sum()
+    }
 
-        eprintln!("best: {} {} {}[s]", cur_turn, best.score, elapsed);
-        let best = best.actions;
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 
-        self.replay = best.iter().map(|s| action::Action::from(*s)).collect();
+    // fire_turn手目で発火する計画
+    pub fn get(&self, fire_turn: usize) -> &[replay::Replay] {
+        if fire_turn == 0 || fire_turn > self.plans.len() {
+            return &[];
+        }
+        &self.plans[fire_turn - 1]
     }
 
-    pub fn replay(&mut self) -> action::Action {
-        if let Some(a) = self.replay.pop_front() {
-            a
-        } else {
-            action::Action::PutBlock { pos: 0, rot: 0 }
+    pub fn insert(&mut self, plan: replay::Replay) {
+        if plan.is_empty() || self.k == 0 {
+            return;
+        }
+        let n = plan.len();
+        if self.plans.len() < n {
+            self.plans.resize(n, Vec::new());
+        }
+
+        let plans = &mut self.plans[n - 1];
+        let actions = plan.get_actions();
+        if plans.iter().any(|p| p.get_actions() == actions) {
+            return;
         }
+        let key = |p: &replay::Replay| (p.get_chains(), p.get_obstacle());
+        let pos = plans.iter().position(|p| key(p) < key(&plan)).unwrap_or(plans.len());
+        plans.insert(pos, plan);
+        plans.truncate(self.k);
     }
 
-    pub fn exists(&self) -> bool {
-        !self.replay.is_empty()
+    pub fn extend(&mut self, plans: &[replay::Replay]) {
+        plans.iter().for_each(|p| self.insert(p.clone()));
     }
 
-    pub fn can_replay(&self, player: &player::Player) -> bool {
-        if let Some(action::Action::UseSkill) = self.replay.front() {
-            player.can_use_skill()
-        } else {
-            self.exists()
+    // 実際に打った手で全計画を1手進める。手が違う計画と発火し終わった計画は捨てる
+    pub fn advance(&mut self, action: &action::Action) {
+        if self.plans.is_empty() {
+            return;
         }
+        self.plans.remove(0);
+        self.plans.iter_mut().for_each(|plans| {
+            plans.retain(|p| p.peek() == Some(action));
+            plans.iter_mut().for_each(|p| { p.replay(); });
+        });
+    }
+
+    // 今の盤面で再生できる計画のうち、連鎖数をlimit_chainsで打ち切って最大のもの (同じなら早く発火するもの)
+    pub fn best(&self, player: &player::Player, enemy_send_obstacles: &[i32], limit_chains: i32) -> Option<&replay::Replay> {
+        let mut max = -1;
+        let mut choosed = None;
+        self.plans.iter().flatten().for_each(|p| {
+            let val = std::cmp::min(limit_chains, p.get_chains() as i32);
+            if max < val && p.can_replay(player, enemy_send_obstacles) {
+                max = val;
+                choosed = Some(p);
+            }
+        });
+        choosed
     }
 }
 
+#[cfg(test)]
+fn plan_test_player() -> player::Player {
+    player::Player::new(super::board::board_test_rensa2(), 0, 0)
+}
 
+#[cfg(test)]
+fn plan_test_replay(player: &player::Player, packs: &[[[u8; 2]; 2]], positions: &[usize]) -> replay::Replay {
+    let actions = positions.iter().map(|pos| action::Action::PutBlock { pos: *pos, rot: 0 }).collect::<Vec<_>>();
+    let mut replay = replay::Replay::new();
+    replay.init(player, &packs[..actions.len()], &[], &actions);
+    replay
+}
+
+#[test]
+fn plan_test_insert() {
+    let player = plan_test_player();
+    let packs = [[[7, 0], [0, 0]], [[7, 0], [0, 0]]];
+    let mut plans = Plans::new(2);
+
+    plans.insert(plan_test_replay(&player, &packs, &[5]));
+    plans.insert(plan_test_replay(&player, &packs, &[2]));
+    plans.insert(plan_test_replay(&player, &packs, &[2]));
+    plans.insert(plan_test_replay(&player, &packs, &[6]));
+    plans.insert(plan_test_replay(&player, &packs, &[5, 2]));
+    assert_eq!(plans.len(), 3);
+    assert_eq!(plans.get(1).iter().map(|p| p.get_chains()).collect::<Vec<_>>(), vec![2, 0]);
+    assert_eq!(plans.get(2).len(), 1);
+    assert!(plans.get(3).is_empty());
+
+    assert_eq!(plans.best(&player, &[], 100).unwrap().get_chains(), 2);
+    assert_eq!(plans.best(&player, &[], 0).unwrap().len(), 1);
+    // 盤面が違うと2連鎖の計画は再生できない
+    assert_eq!(plans.best(&player::Player::default(), &[], 100).unwrap().get_chains(), 0);
+}
+
+#[test]
+fn plan_test_advance() {
+    let player = plan_test_player();
+    let packs = [[[7, 0], [0, 0]], [[7, 0], [0, 0]], [[7, 0], [0, 0]]];
+    let mut plans = Plans::new(4);
+    plans.insert(plan_test_replay(&player, &packs, &[2]));
+    plans.insert(plan_test_replay(&player, &packs, &[5, 2]));
+    plans.insert(plan_test_replay(&player, &packs, &[6, 2]));
+    plans.insert(plan_test_replay(&player, &packs, &[5, 8, 2]));
+
+    let put = action::Action::PutBlock { pos: 5, rot: 0 };
+    plans.advance(&put);
+    assert_eq!(plans.len(), 2);
+    assert_eq!(plans.get(1)[0].get_actions(), vec![action::Action::PutBlock { pos: 2, rot: 0 }]);
+
+    let mut next = player.clone();
+    next.put(&packs[0], &put);
+    assert_eq!(plans.best(&next, &[], 100).unwrap().get_chains(), 2);
+
+    plans.advance(&put);
+    plans.advance(&put);
+    assert!(plans.is_empty());
+}
@@ -1,6 +1,7 @@
 
 use super::action;
 use super::board;
+use super::rand;
 
 use super::consts::*;
 
@@ -27,7 +28,7 @@ impl Player {
             action::Action::PutBlock { pos, rot } => {
                 let result = self.board.put(pack, *pos, *rot);
                 if result.chains > 0 {
-                    self.skill_guage += 8;
+                    self.skill_guage = std::cmp::min(self.skill_guage + 8, MAX_SKILL_GUAGE);
                 }
                 result
             },
@@ -46,11 +47,78 @@ impl Player {
         self.obstacle += obstacle;
     }
 
+    // 相殺しきれなかった分を相手に送るお邪魔として取り出す
+    pub fn take_send_obstacles(&mut self) -> i32 {
+        let send = std::cmp::max(-self.obstacle, 0);
+        self.obstacle = std::cmp::max(self.obstacle, 0);
+        send
+    }
+
+    pub fn decrease_skill_guage(&mut self, v: i32) {
+        self.skill_guage = std::cmp::max(self.skill_guage - v, 0);
+    }
+
     pub fn can_use_skill(&self) -> bool {
         self.skill_guage >= 80
     }
 
+    // 盤面とお邪魔のストックとスキルゲージ全体のハッシュ
     pub fn hash(&self) -> u64 {
-        self.board.hash()
+        let state = (self.obstacle as u32 as u64) << 32 | self.skill_guage as u32 as u64;
+        self.board.hash() ^ rand::splitmix64(state ^ 0x5851F42D4C957F2D)
     }
 }
+
+impl std::fmt::Debug for Player {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "obstacle={} skill_guage={}", self.obstacle, self.skill_guage)?;
+        write!(f, "{:?}", self.board)
+    }
+}
+
+// Debugの出力を読む。"obstacle=N skill_guage=N"の行は省略でき、その後は盤面
+impl std::str::FromStr for Player {
+    type Err = board::ParseBoardError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut player = Player::default();
+        let mut lines = s.trim_start().splitn(2, '\n');
+        let first = lines.next().unwrap_or("");
+        if !first.contains('=') {
+            player.board = s.parse()?;
+            return Ok(player);
+        }
+        for field in first.split_whitespace() {
+            let value = match field.split_once('=') {
+                Some(("obstacle", v)) => v.parse().map(|v| player.obstacle = v),
+                Some(("skill_guage", v)) => v.parse().map(|v| player.skill_guage = v),
+                _ => return Err(board::ParseBoardError { row: 0, message: format!("unknown field {:?}", field) }),
+            };
+            value.map_err(|_| board::ParseBoardError { row: 0, message: format!("invalid field {:?}", field) })?;
+        }
+        player.board = lines.next().unwrap_or("").parse()?;
+        Ok(player)
+    }
+}
+
+#[test]
+fn player_test_hash() {
+    let board: board::Board = "1.........".parse().unwrap();
+    let player = Player::new(board.clone(), 3, 40);
+    assert_eq!(player.hash(), Player::new(board.clone(), 3, 40).hash());
+    assert_ne!(player.hash(), Player::new(board.clone(), 4, 40).hash());
+    assert_ne!(player.hash(), Player::new(board.clone(), 3, 48).hash());
+    assert_ne!(player.hash(), Player::new(board::Board::new(), 3, 40).hash());
+    assert_ne!(Player::new(board.clone(), 0, 1).hash(), Player::new(board, 1, 0).hash());
+}
+
+#[test]
+fn player_test_from_str() {
+    let mut player = Player::new("0500000000/0800000000/X830000000".parse().unwrap(), 12, 40);
+    assert_eq!(format!("{:?}", player).parse::<Player>().unwrap(), player);
+    player.obstacle = 0;
+    player.skill_guage = 0;
+    assert_eq!(player.board.to_compact().parse::<Player>().unwrap(), player);
+    assert!("obstacle=x\n".parse::<Player>().is_err());
+    assert!("guage=1\n".parse::<Player>().is_err());
+}
@@ -0,0 +1,281 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+
+use super::action;
+use super::board;
+use super::player;
+use super::types::RawPack;
+
+use super::consts::{W,H,MAX_TURN};
+
+#[derive(Debug)]
+pub enum ProtocolErrorKind {
+    Io(std::io::Error),
+    UnexpectedEof,
+    InvalidToken { expected: &'static str },
+}
+
+#[derive(Debug)]
+pub struct ProtocolError {
+    pub line: usize,
+    pub token: String,
+    pub kind: ProtocolErrorKind,
+}
+
+impl ProtocolError {
+    pub fn is_eof(&self) -> bool {
+        matches!(self.kind, ProtocolErrorKind::UnexpectedEof)
+    }
+}
+
+impl std::fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.kind {
+            ProtocolErrorKind::Io(e) => write!(f, "line {}: {}", self.line, e),
+            ProtocolErrorKind::UnexpectedEof => write!(f, "line {}: unexpected end of input", self.line),
+            ProtocolErrorKind::InvalidToken { expected } => write!(f, "line {}: expected {}, found {:?}", self.line, expected, self.token),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct GameInput {
+    pub packs: Vec<RawPack>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PlayerInput {
+    pub rest_time_in_milli: usize,
+    pub obstacle: i32,
+    pub skill_guage: i32,
+    pub score: i32,
+    pub board: board::Board,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TurnInput {
+    pub turn: usize,
+    pub player: PlayerInput,
+    pub enemy: PlayerInput,
+}
+
+impl GameInput {
+    pub fn write<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        for p in self.packs.iter() {
+            write!(w, "{} {}\n{} {}\nEND\n", p[0][0], p[0][1], p[1][0], p[1][1])?;
+        }
+        Ok(())
+    }
+}
+
+impl PlayerInput {
+    pub fn from_player(player: &player::Player, rest_time_in_milli: usize, score: i32) -> Self {
+        Self {
+            rest_time_in_milli,
+            obstacle: player.obstacle,
+            skill_guage: player.skill_guage,
+            score,
+            board: player.board.clone(),
+        }
+    }
+
+    pub fn to_player(&self) -> player::Player {
+        player::Player::new(self.board.clone(), self.obstacle, self.skill_guage)
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        writeln!(w, "{}\n{}\n{}\n{}", self.rest_time_in_milli, self.obstacle, self.skill_guage, self.score)?;
+        let board = self.board.to_board();
+        for row in board.chunks(W) {
+            let row = row.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+            writeln!(w, "{}", row.join(" "))?;
+        }
+        writeln!(w, "END")
+    }
+}
+
+impl TurnInput {
+    pub fn write<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        writeln!(w, "{}", self.turn)?;
+        self.player.write(w)?;
+        self.enemy.write(w)
+    }
+}
+
+// 空白区切りのトークンを行番号付きで読む
+pub struct Parser<R> {
+    reader: R,
+    line: usize,
+    tokens: VecDeque<String>,
+}
+
+impl<R: BufRead> Parser<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader, line: 0, tokens: VecDeque::new(), }
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    // 次のトークンの前に入力が終わっているか
+    pub fn at_eof(&mut self) -> Result<bool, ProtocolError> {
+        while self.tokens.is_empty() {
+            if !self.read_line()? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn read_line(&mut self) -> Result<bool, ProtocolError> {
+        let mut buf = String::new();
+        let n = self.reader.read_line(&mut buf).map_err(|e| self.error(String::new(), ProtocolErrorKind::Io(e)))?;
+        if n == 0 {
+            return Ok(false);
+        }
+        self.line += 1;
+        self.tokens = buf.split_whitespace().map(|t| t.to_string()).collect();
+        Ok(true)
+    }
+
+    fn error(&self, token: String, kind: ProtocolErrorKind) -> ProtocolError {
+        ProtocolError { line: self.line, token, kind }
+    }
+
+    fn next_token(&mut self) -> Result<String, ProtocolError> {
+        while self.tokens.is_empty() {
+            if !self.read_line()? {
+                return Err(self.error(String::new(), ProtocolErrorKind::UnexpectedEof));
+            }
+        }
+        Ok(self.tokens.pop_front().unwrap())
+    }
+
+    fn read<T: FromStr>(&mut self, expected: &'static str) -> Result<T, ProtocolError> {
+        let token = self.next_token()?;
+        token.parse().map_err(|_| self.error(token, ProtocolErrorKind::InvalidToken { expected }))
+    }
+
+    fn read_end(&mut self) -> Result<(), ProtocolError> {
+        let token = self.next_token()?;
+        if token != "END" {
+            return Err(self.error(token, ProtocolErrorKind::InvalidToken { expected: "END" }));
+        }
+        Ok(())
+    }
+
+    fn read_cell(&mut self, expected: &'static str) -> Result<u8, ProtocolError> {
+        let token = self.next_token()?;
+        match token.parse::<u8>() {
+            Ok(v) if v <= 9 || v as u64 == super::consts::OBSTACLE => Ok(v),
+            _ => Err(self.error(token, ProtocolErrorKind::InvalidToken { expected })),
+        }
+    }
+
+    pub fn read_game_input(&mut self) -> Result<GameInput, ProtocolError> {
+        let mut packs = Vec::with_capacity(MAX_TURN);
+        for _ in 0..MAX_TURN {
+            let mut pack = [[0; 2]; 2];
+            for v in pack.iter_mut().flatten() {
+                *v = self.read_cell("pack block")?;
+            }
+            self.read_end()?;
+            packs.push(pack);
+        }
+        Ok(GameInput { packs })
+    }
+
+    fn read_player_input(&mut self) -> Result<PlayerInput, ProtocolError> {
+        let rest_time_in_milli = self.read("rest time")?;
+        let obstacle = self.read("obstacle")?;
+        let skill_guage = self.read("skill guage")?;
+        let score = self.read("score")?;
+        let mut board = [0; W * H];
+        for v in board.iter_mut() {
+            *v = self.read_cell("board block")?;
+        }
+        self.read_end()?;
+        Ok(PlayerInput { rest_time_in_milli, obstacle, skill_guage, score, board: board::Board::from_board(board) })
+    }
+
+    pub fn read_turn_input(&mut self) -> Result<TurnInput, ProtocolError> {
+        let turn = self.read("turn")?;
+        let player = self.read_player_input()?;
+        let enemy = self.read_player_input()?;
+        Ok(TurnInput { turn, player, enemy })
+    }
+
+    // AIの出力 ("pos rot" か "S")
+    pub fn read_action(&mut self) -> Result<action::Action, ProtocolError> {
+        let token = self.next_token()?;
+        if token == "S" {
+            return Ok(action::Action::UseSkill);
+        }
+        let pos = match token.parse::<usize>() {
+            Ok(pos) if pos < W - 1 => pos,
+            _ => return Err(self.error(token, ProtocolErrorKind::InvalidToken { expected: "position or S" })),
+        };
+        let token = self.next_token()?;
+        match token.parse::<usize>() {
+            Ok(rot) if rot < 4 => Ok(action::Action::PutBlock { pos, rot }),
+            _ => Err(self.error(token, ProtocolErrorKind::InvalidToken { expected: "rotation" })),
+        }
+    }
+}
+
+#[cfg(test)]
+fn protocol_test_turn_input() -> TurnInput {
+    let mut player = player::Player::new(board::Board::new(), 12, 40);
+    player.board.put(&[[1, 3], [11, 0]], 4, 1);
+    let enemy = player::Player::new(board::Board::new(), 0, 100);
+    TurnInput {
+        turn: 7,
+        player: PlayerInput::from_player(&player, 170000, 25),
+        enemy: PlayerInput::from_player(&enemy, 180000, 0),
+    }
+}
+
+#[test]
+fn protocol_test_round_trip() {
+    let game = GameInput { packs: (0..MAX_TURN).map(|i| [[(i % 9 + 1) as u8, 0], [11, (i % 7) as u8]]).collect() };
+    let turn = protocol_test_turn_input();
+    let mut buf = Vec::new();
+    game.write(&mut buf).unwrap();
+    turn.write(&mut buf).unwrap();
+    buf.extend_from_slice(b"3 2\nS\n");
+
+    let mut parser = Parser::new(&buf[..]);
+    assert_eq!(parser.read_game_input().unwrap(), game);
+    assert_eq!(parser.read_turn_input().unwrap(), turn);
+    assert_eq!(parser.read_action().unwrap(), action::Action::PutBlock { pos: 3, rot: 2 });
+    assert_eq!(parser.read_action().unwrap(), action::Action::UseSkill);
+    assert!(parser.at_eof().unwrap());
+    assert!(parser.read_turn_input().unwrap_err().is_eof());
+
+    let mut parser = Parser::new("\n  \n3".as_bytes());
+    assert!(!parser.at_eof().unwrap());
+    assert_eq!(parser.line(), 3);
+}
+
+#[test]
+fn protocol_test_error_context() {
+    let mut buf = Vec::new();
+    protocol_test_turn_input().write(&mut buf).unwrap();
+    let text = String::from_utf8(buf).unwrap().replacen("\n12\n", "\nx\n", 1);
+
+    let e = Parser::new(text.as_bytes()).read_turn_input().unwrap_err();
+    assert_eq!(e.line, 3);
+    assert_eq!(e.token, "x");
+    assert_eq!(e.to_string(), "line 3: expected obstacle, found \"x\"");
+
+    let e = Parser::new("7\n1000\n".as_bytes()).read_turn_input().unwrap_err();
+    assert!(e.is_eof());
+    assert_eq!(e.line, 2);
+
+    let e = Parser::new("9 0\n".as_bytes()).read_action().unwrap_err();
+    assert_eq!(e.token, "9");
+}
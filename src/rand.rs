@@ -1,22 +1,164 @@
+use std::sync::atomic::{AtomicU64, Ordering};
 
+// xorshift64*
+// 内部状態はxorshiftの線形変換で進め、出力だけ乗算で混ぜる
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct XorShiftL {
+    x: u64,
+}
 
+const MULTIPLIER: u64 = 0x2545F4914F6CDD1D;
 
+// スレッドごとの乱数列の間隔 (これだけ進めても他のスレッドの列と重ならない)
+pub const STREAM_JUMP: u64 = 1 << 48;
 
-pub struct XorShiftL {
-    x: u64,
+// 64bitの値をよく混ぜる全単射 (ハッシュにも使う)
+pub fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+fn step(x: u64) -> u64 {
+    let x = x ^ (x >> 12);
+    let x = x ^ (x << 25);
+    x ^ (x >> 27)
+}
+
+// GF(2)上の64x64行列。m[j]はj番目の単位ベクトルの行き先
+type Matrix = [u64; 64];
+
+fn apply(m: &Matrix, v: u64) -> u64 {
+    (0..64).filter(|j| v >> j & 1 == 1).fold(0, |acc, j| acc ^ m[j])
+}
+
+fn compose(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = [0; 64];
+    m.iter_mut().zip(b.iter()).for_each(|(m, b)| *m = apply(a, *b));
+    m
 }
 
 impl XorShiftL {
+    // 時刻とプロセス内の通し番号から種を決める
     pub fn new() -> Self {
-        Self { x: 88172645463325252, }
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0);
+        let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+        Self::from_seed(nanos ^ splitmix64(count ^ std::process::id() as u64))
     }
 
+    // 同じseedなら同じ列になる。0でも良い
     pub fn from_seed(seed: u64) -> Self {
-        Self { x: seed, }
+        let x = splitmix64(seed);
+        Self { x: if x == 0 { MULTIPLIER } else { x }, }
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> u64 {
-        self.x = self.x ^ (self.x << 7);
-        self.x
+        self.x = step(self.x);
+        self.x.wrapping_mul(MULTIPLIER)
+    }
+
+    // [range.start, range.end) から一様に選ぶ
+    pub fn gen_range(&mut self, range: std::ops::Range<u64>) -> u64 {
+        assert!(range.start < range.end, "empty range");
+        let n = range.end - range.start;
+        range.start + ((self.next() as u128 * n as u128) >> 64) as u64
+    }
+
+    pub fn shuffle<T>(&mut self, v: &mut [T]) {
+        (1..v.len()).rev().for_each(|i| {
+            let j = self.gen_range(0..i as u64 + 1) as usize;
+            v.swap(i, j);
+        });
+    }
+
+    // next()をn回呼んだのと同じ状態にする
+    pub fn jump(&mut self, mut n: u64) {
+        let mut m: Matrix = [0; 64];
+        m.iter_mut().enumerate().for_each(|(j, m)| *m = step(1 << j));
+        while n > 0 {
+            if n & 1 == 1 {
+                self.x = apply(&m, self.x);
+            }
+            m = compose(&m, &m);
+            n >>= 1;
+        }
     }
+
+    // i番目のスレッド用の乱数列
+    pub fn stream(&self, i: usize) -> Self {
+        let mut rand = self.clone();
+        rand.jump(STREAM_JUMP.wrapping_mul(i as u64));
+        rand
+    }
+}
+
+impl Default for XorShiftL {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn rand_test_mean() {
+    let mut rand = XorShiftL::from_seed(1);
+    let n = 100000;
+    let mean = (0..n).map(|_| rand.next() as f64 / u64::MAX as f64).sum::<f64>() / n as f64;
+    assert!((mean - 0.5).abs() < 0.01, "mean={}", mean);
+}
+
+#[test]
+fn rand_test_distribution() {
+    let mut rand = XorShiftL::from_seed(2);
+    let n = 100000;
+    let mut buckets = [0; 10];
+    let mut bits = [0; 64];
+    (0..n).for_each(|_| {
+        buckets[rand.gen_range(0..10) as usize] += 1;
+        let x = rand.next();
+        bits.iter_mut().enumerate().for_each(|(i, b)| *b += x >> i & 1);
+    });
+
+    // 自由度9のカイ二乗値 (p=0.001で27.9)
+    let expected = n as f64 / 10.0;
+    let chi2 = buckets.iter().map(|b| (*b as f64 - expected).powi(2) / expected).sum::<f64>();
+    assert!(chi2 < 27.9, "chi2={} buckets={:?}", chi2, buckets);
+    // 各ビットが半分ずつ立つ (標準偏差は約158)
+    bits.iter().for_each(|b| assert!((*b as i64 - n / 2).abs() < 1000, "bits={:?}", bits));
+}
+
+#[test]
+fn rand_test_seed() {
+    assert_eq!(XorShiftL::from_seed(3).next(), XorShiftL::from_seed(3).next());
+    assert_ne!(XorShiftL::from_seed(3).next(), XorShiftL::from_seed(4).next());
+    let mut zero = XorShiftL::from_seed(0);
+    assert!((0..4).map(|_| zero.next()).all(|x| x != 0));
+    assert_ne!(XorShiftL::new().next(), XorShiftL::new().next());
+}
+
+#[test]
+fn rand_test_jump() {
+    let mut a = XorShiftL::from_seed(5);
+    let mut b = a.clone();
+    (0..1000).for_each(|_| { a.next(); });
+    b.jump(1000);
+    assert_eq!(a, b);
+    assert_eq!(a.next(), b.next());
+
+    let base = XorShiftL::from_seed(6);
+    assert_eq!(base.stream(0), base);
+    assert_ne!(base.stream(1).next(), base.stream(2).next());
+}
+
+#[test]
+fn rand_test_shuffle() {
+    let mut rand = XorShiftL::from_seed(7);
+    let mut v = (0..20).collect::<Vec<_>>();
+    rand.shuffle(&mut v);
+    assert_ne!(v, (0..20).collect::<Vec<_>>());
+    v.sort();
+    assert_eq!(v, (0..20).collect::<Vec<_>>());
+    assert!((0..1000).all(|_| rand.gen_range(3..5) >= 3));
 }
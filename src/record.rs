@@ -0,0 +1,680 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use super::action;
+use super::board;
+use super::game;
+use super::types::RawPack;
+
+use super::consts::*;
+
+// 1ターンの片方のプレイヤー。盤面などは行動する前の状態
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SideRecord {
+    pub board: board::Board,
+    pub obstacle: i32,
+    pub skill_guage: i32,
+    pub score: i32,
+    pub action: action::Action,
+    pub result: action::ActionResult,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TurnRecord {
+    pub turn: usize,
+    pub sides: [SideRecord; 2],
+}
+
+// 1試合の全記録
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct GameRecord {
+    pub packs: Vec<RawPack>,
+    pub turns: Vec<TurnRecord>,
+    pub outcome: Option<game::Outcome>,
+}
+
+#[derive(Debug)]
+pub struct RecordError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for RecordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for RecordError {}
+
+impl From<std::io::Error> for RecordError {
+    fn from(e: std::io::Error) -> Self {
+        RecordError { line: 0, message: e.to_string() }
+    }
+}
+
+fn error<T>(line: usize, message: impl Into<String>) -> Result<T, RecordError> {
+    Err(RecordError { line, message: message.into() })
+}
+
+// 盤面は上の行から順に、Debugと同じ文字 (お邪魔はX) で1行ずつ並べる
+fn board_rows(board: &board::Board) -> Vec<String> {
+    board.to_compact().split('/').map(|r| r.to_string()).collect()
+}
+
+fn parse_board_rows(rows: &[&str]) -> Option<board::Board> {
+    if rows.len() != H {
+        return None;
+    }
+    rows.join("/").parse().ok()
+}
+
+fn format_action(action: &action::Action) -> String {
+    match action {
+        action::Action::PutBlock { pos, rot } => format!("{},{}", pos, rot),
+        action::Action::UseSkill => "S".to_string(),
+    }
+}
+
+fn parse_action(s: &str) -> Option<action::Action> {
+    if s == "S" {
+        return Some(action::Action::UseSkill);
+    }
+    let (pos, rot) = s.split_once(',')?;
+    let (pos, rot) = (pos.parse().ok()?, rot.parse().ok()?);
+    if pos < W - 1 && rot < 4 { Some(action::Action::PutBlock { pos, rot }) } else { None }
+}
+
+fn format_result(r: &action::ActionResult) -> String {
+    format!("{},{},{},{},{}", r.chains, r.obstacle, r.skill_guage, r.fire_height, r.score)
+}
+
+fn parse_result(s: &str) -> Option<action::ActionResult> {
+    let v = s.split(',').collect::<Vec<_>>();
+    if v.len() != 5 {
+        return None;
+    }
+    Some(action::ActionResult::new(v[0].parse().ok()?, v[1].parse().ok()?, v[2].parse().ok()?, v[3].parse().ok()?, v[4].parse().ok()?))
+}
+
+fn parse_pack(values: &[i64]) -> Option<RawPack> {
+    if values.len() != 4 || values.iter().any(|v| !(0..=9).contains(v) && *v as u64 != OBSTACLE) {
+        return None;
+    }
+    Some([[values[0] as u8, values[1] as u8], [values[2] as u8, values[3] as u8]])
+}
+
+// 記録と再計算の最初の食い違い
+#[derive(Debug)]
+pub struct Divergence {
+    pub turn: usize,
+    pub side: Option<usize>,
+    pub what: &'static str,
+    pub recorded: String,
+    pub simulated: String,
+    pub boards: Option<Box<(board::Board, board::Board)>>,
+}
+
+impl std::fmt::Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "turn {}", self.turn)?;
+        if let Some(side) = self.side {
+            write!(f, " side {}", side)?;
+        }
+        write!(f, ": {} differs (recorded {}, simulated {})", self.what, self.recorded, self.simulated)?;
+        if let Some(boards) = &self.boards {
+            let (recorded, simulated) = boards.as_ref();
+            // 左が記録、右が再計算。違う行に*を付ける
+            write!(f, "\n{:<w$} simulated", "recorded", w = W)?;
+            for (r, s) in board_rows(recorded).iter().zip(board_rows(simulated).iter()) {
+                write!(f, "\n{} {}{}", r, s, if r != s { " *" } else { "" })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Divergence {}
+
+impl SideRecord {
+    pub fn new(game: &game::Game, side: usize, action: &action::Action, result: action::ActionResult) -> Self {
+        let player = game.player(side);
+        Self {
+            board: player.board.clone(),
+            obstacle: player.obstacle,
+            skill_guage: player.skill_guage,
+            score: game.score(side),
+            action: action.clone(),
+            result,
+        }
+    }
+}
+
+impl GameRecord {
+    pub fn new(packs: Vec<RawPack>) -> Self {
+        Self { packs, turns: Vec::new(), outcome: None }
+    }
+
+    // 記録しながら1ターン進める
+    pub fn step(&mut self, game: &mut game::Game, actions: [&action::Action; 2]) -> [action::ActionResult; 2] {
+        let before = game.clone();
+        let results = game.step(actions);
+        let sides = [0, 1].map(|i| SideRecord::new(&before, i, actions[i], results[i].clone()));
+        self.turns.push(TurnRecord { turn: before.turn(), sides });
+        self.outcome = game.outcome();
+        results
+    }
+
+    // 反則負けなどstep以外で決着したときに呼ぶ
+    pub fn finish(&mut self, game: &game::Game) {
+        self.outcome = game.outcome();
+    }
+
+    // パックと両者の行動から試合を再計算し、記録された盤面や結果と食い違う最初の所を返す
+    pub fn verify(&self) -> Result<(), Divergence> {
+        let mut game = game::Game::new(self.packs.clone());
+        for (i, t) in self.turns.iter().enumerate() {
+            let diverge = |side, what, recorded: String, simulated: String| Divergence { turn: t.turn, side, what, recorded, simulated, boards: None };
+            if t.turn != i {
+                return Err(diverge(None, "turn", t.turn.to_string(), i.to_string()));
+            }
+            if game.is_over() {
+                return Err(diverge(None, "outcome", "not over".to_string(), format!("{:?}", game.outcome().unwrap())));
+            }
+
+            for (side, s) in t.sides.iter().enumerate() {
+                let player = game.player(side);
+                if s.board != player.board {
+                    let mut d = diverge(Some(side), "board", String::new(), String::new());
+                    d.recorded = format!("{} blocks", s.board.to_board().iter().filter(|c| **c != 0).count());
+                    d.simulated = format!("{} blocks", player.board.to_board().iter().filter(|c| **c != 0).count());
+                    d.boards = Some(Box::new((s.board.clone(), player.board.clone())));
+                    return Err(d);
+                }
+                let fields = [("obstacle", s.obstacle, player.obstacle), ("skill_guage", s.skill_guage, player.skill_guage), ("score", s.score, game.score(side))];
+                if let Some((what, recorded, simulated)) = fields.iter().find(|f| f.1 != f.2) {
+                    return Err(diverge(Some(side), what, recorded.to_string(), simulated.to_string()));
+                }
+            }
+
+            let results = game.step([&t.sides[0].action, &t.sides[1].action]);
+            for (side, s) in t.sides.iter().enumerate() {
+                if s.result != results[side] {
+                    let mut d = diverge(Some(side), "result", format_result(&s.result), format_result(&results[side]));
+                    // 次のターンの記録があれば行動後の盤面を並べる
+                    d.boards = self.turns.get(i + 1).map(|n| Box::new((n.sides[side].board.clone(), game.player(side).board.clone())));
+                    return Err(d);
+                }
+            }
+        }
+
+        // 途中で打ち切られた記録 (反則負けなど) は最後まで再計算できないので、決着した場合だけ比べる
+        if game.is_over() && game.outcome() != self.outcome {
+            let turn = self.turns.last().map_or(0, |t| t.turn);
+            return Err(Divergence { turn, side: None, what: "outcome", recorded: format!("{:?}", self.outcome), simulated: format!("{:?}", game.outcome()), boards: None });
+        }
+        Ok(())
+    }
+
+    // テキスト形式
+    // record v1
+    // pack 8 3 1 6             (1パック1行)
+    // turn 0
+    // side 0 obstacle=0 skill_guage=0 score=0 action=3,1 result=0,0,0,0,0 board=0000000000/...
+    // side 1 ...
+    // outcome win 0            (win <side> か draw。決着していなければ無し)
+    pub fn write_text<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        writeln!(w, "record v1")?;
+        for p in self.packs.iter() {
+            writeln!(w, "pack {} {} {} {}", p[0][0], p[0][1], p[1][0], p[1][1])?;
+        }
+        for t in self.turns.iter() {
+            writeln!(w, "turn {}", t.turn)?;
+            for (i, s) in t.sides.iter().enumerate() {
+                writeln!(w, "side {} obstacle={} skill_guage={} score={} action={} result={} board={}",
+                    i, s.obstacle, s.skill_guage, s.score, format_action(&s.action), format_result(&s.result), s.board.to_compact())?;
+            }
+        }
+        match self.outcome {
+            Some(game::Outcome::Win(side)) => writeln!(w, "outcome win {}", side),
+            Some(game::Outcome::Draw) => writeln!(w, "outcome draw"),
+            None => Ok(()),
+        }
+    }
+
+    pub fn read_text<R: BufRead>(reader: R) -> Result<Self, RecordError> {
+        let mut record = Self::new(Vec::new());
+        let mut sides: Vec<SideRecord> = Vec::new();
+        let mut turn = None;
+        let mut header = false;
+        let mut n = 0;
+
+        for line in reader.lines() {
+            let line = line?;
+            n += 1;
+            let tokens = line.split_whitespace().collect::<Vec<_>>();
+            if tokens.is_empty() {
+                continue;
+            }
+            if !header {
+                if tokens != ["record", "v1"] {
+                    return error(n, "expected \"record v1\"");
+                }
+                header = true;
+                continue;
+            }
+            match tokens[0] {
+                "pack" => {
+                    let values = tokens[1..].iter().map(|t| t.parse().ok()).collect::<Option<Vec<i64>>>();
+                    match values.as_deref().and_then(parse_pack) {
+                        Some(pack) => record.packs.push(pack),
+                        None => return error(n, format!("invalid pack {:?}", line)),
+                    }
+                },
+                "turn" => {
+                    if turn.is_some() {
+                        return error(n, "turn has fewer than 2 sides");
+                    }
+                    match tokens.get(1).and_then(|t| t.parse().ok()) {
+                        Some(t) => turn = Some(t),
+                        None => return error(n, format!("invalid turn {:?}", line)),
+                    }
+                },
+                "side" => {
+                    if turn.is_none() || tokens.get(1) != Some(&sides.len().to_string().as_str()) {
+                        return error(n, "unexpected side");
+                    }
+                    sides.push(Self::parse_side(&tokens[2..]).ok_or_else(|| RecordError { line: n, message: format!("invalid side {:?}", line) })?);
+                    if sides.len() == 2 {
+                        let sides = [sides.remove(0), sides.remove(0)];
+                        record.turns.push(TurnRecord { turn: turn.take().unwrap(), sides });
+                    }
+                },
+                "outcome" => {
+                    record.outcome = match &tokens[1..] {
+                        ["draw"] => Some(game::Outcome::Draw),
+                        ["win", side] if *side == "0" || *side == "1" => Some(game::Outcome::Win(side.parse().unwrap())),
+                        _ => return error(n, format!("invalid outcome {:?}", line)),
+                    };
+                },
+                _ => return error(n, format!("unknown line {:?}", line)),
+            }
+        }
+        if !header {
+            return error(n, "empty record");
+        }
+        if turn.is_some() {
+            return error(n, "turn has fewer than 2 sides");
+        }
+        Ok(record)
+    }
+
+    fn parse_side(fields: &[&str]) -> Option<SideRecord> {
+        let fields = fields.iter().map(|f| f.split_once('=')).collect::<Option<BTreeMap<_, _>>>()?;
+        if fields.len() != 6 {
+            return None;
+        }
+        Some(SideRecord {
+            board: parse_board_rows(&fields.get("board")?.split('/').collect::<Vec<_>>())?,
+            obstacle: fields.get("obstacle")?.parse().ok()?,
+            skill_guage: fields.get("skill_guage")?.parse().ok()?,
+            score: fields.get("score")?.parse().ok()?,
+            action: parse_action(fields.get("action")?)?,
+            result: parse_result(fields.get("result")?)?,
+        })
+    }
+
+    // JSON形式 (盤面は上の行から順に文字列で並べる)
+    pub fn write_json<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        let packs = self.packs.iter().map(|p| format!("[[{},{}],[{},{}]]", p[0][0], p[0][1], p[1][0], p[1][1])).collect::<Vec<_>>();
+        write!(w, "{{\"packs\":[{}],\"turns\":[", packs.join(","))?;
+        for (i, t) in self.turns.iter().enumerate() {
+            if i > 0 {
+                write!(w, ",")?;
+            }
+            write!(w, "\n{{\"turn\":{},\"sides\":[", t.turn)?;
+            for (j, s) in t.sides.iter().enumerate() {
+                let r = &s.result;
+                let rows = board_rows(&s.board).iter().map(|r| format!("\"{}\"", r)).collect::<Vec<_>>();
+                write!(w, "{}{{\"obstacle\":{},\"skill_guage\":{},\"score\":{},\"action\":\"{}\",\"result\":{{\"chains\":{},\"obstacle\":{},\"skill_guage\":{},\"fire_height\":{},\"score\":{}}},\"board\":[{}]}}",
+                    if j > 0 { "," } else { "" }, s.obstacle, s.skill_guage, s.score, format_action(&s.action),
+                    r.chains, r.obstacle, r.skill_guage, r.fire_height, r.score, rows.join(","))?;
+            }
+            write!(w, "]}}")?;
+        }
+        let outcome = match self.outcome {
+            Some(game::Outcome::Win(side)) => format!("{{\"win\":{}}}", side),
+            Some(game::Outcome::Draw) => "\"draw\"".to_string(),
+            None => "null".to_string(),
+        };
+        writeln!(w, "],\n\"outcome\":{}}}", outcome)
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, RecordError> {
+        let value = json::parse(text).map_err(|(line, message)| RecordError { line, message })?;
+        Self::from_json_value(&value).ok_or_else(|| RecordError { line: 0, message: "unexpected json structure".to_string() })
+    }
+
+    fn from_json_value(value: &json::Value) -> Option<Self> {
+        let packs = value.get("packs")?.as_array()?.iter().map(|p| {
+            let values = p.as_array()?.iter().map(|row| row.as_array()).collect::<Option<Vec<_>>>()?;
+            let values = values.iter().flat_map(|row| row.iter()).map(|v| v.as_i64()).collect::<Option<Vec<_>>>()?;
+            parse_pack(&values)
+        }).collect::<Option<Vec<_>>>()?;
+
+        let turns = value.get("turns")?.as_array()?.iter().map(|t| {
+            let sides = t.get("sides")?.as_array()?.iter().map(|s| {
+                let r = s.get("result")?;
+                let int = |v: &json::Value, key: &str| v.get(key)?.as_i64();
+                let rows = s.get("board")?.as_array()?.iter().map(|r| r.as_str()).collect::<Option<Vec<_>>>()?;
+                Some(SideRecord {
+                    board: parse_board_rows(&rows)?,
+                    obstacle: int(s, "obstacle")? as i32,
+                    skill_guage: int(s, "skill_guage")? as i32,
+                    score: int(s, "score")? as i32,
+                    action: parse_action(s.get("action")?.as_str()?)?,
+                    result: action::ActionResult::new(int(r, "chains")? as u8, int(r, "obstacle")? as i32, int(r, "skill_guage")? as i32, int(r, "fire_height")? as i8, int(r, "score")? as i32),
+                })
+            }).collect::<Option<Vec<_>>>()?;
+            if sides.len() != 2 {
+                return None;
+            }
+            let mut sides = sides.into_iter();
+            Some(TurnRecord { turn: t.get("turn")?.as_i64()? as usize, sides: [sides.next()?, sides.next()?] })
+        }).collect::<Option<Vec<_>>>()?;
+
+        let outcome = match value.get("outcome")? {
+            json::Value::Null => None,
+            json::Value::Str(s) if s == "draw" => Some(game::Outcome::Draw),
+            v => Some(game::Outcome::Win(v.get("win")?.as_i64().filter(|s| *s == 0 || *s == 1)? as usize)),
+        };
+        Some(Self { packs, turns, outcome })
+    }
+
+    // 拡張子が.jsonならJSON、それ以外はテキスト形式
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let is_json = path.as_ref().extension().is_some_and(|e| e == "json");
+        let mut w = BufWriter::new(std::fs::File::create(path)?);
+        if is_json { self.write_json(&mut w)? } else { self.write_text(&mut w)? }
+        w.flush()
+    }
+
+    // 中身を見てJSONかテキスト形式かを判定する
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, RecordError> {
+        let mut text = String::new();
+        BufReader::new(std::fs::File::open(path)?).read_to_string(&mut text)?;
+        if text.trim_start().starts_with('{') {
+            Self::from_json(&text)
+        } else {
+            Self::read_text(text.as_bytes())
+        }
+    }
+}
+
+// 記録を読むのに必要なだけのJSONパーサ
+mod json {
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, PartialEq)]
+    pub enum Value {
+        Null,
+        Bool(bool),
+        Num(f64),
+        Str(String),
+        Array(Vec<Value>),
+        Object(BTreeMap<String, Value>),
+    }
+
+    impl Value {
+        pub fn get(&self, key: &str) -> Option<&Value> {
+            match self { Value::Object(m) => m.get(key), _ => None }
+        }
+
+        pub fn as_array(&self) -> Option<&[Value]> {
+            match self { Value::Array(v) => Some(v), _ => None }
+        }
+
+        pub fn as_str(&self) -> Option<&str> {
+            match self { Value::Str(s) => Some(s), _ => None }
+        }
+
+        pub fn as_i64(&self) -> Option<i64> {
+            match self { Value::Num(v) if v.fract() == 0.0 => Some(*v as i64), _ => None }
+        }
+    }
+
+    struct Parser<'a> {
+        s: &'a [u8],
+        pos: usize,
+    }
+
+    type Result<T> = std::result::Result<T, (usize, String)>;
+
+    // エラーは (行番号, メッセージ)
+    pub fn parse(text: &str) -> Result<Value> {
+        let mut p = Parser { s: text.as_bytes(), pos: 0 };
+        let v = p.value()?;
+        p.skip_ws();
+        if p.pos != p.s.len() {
+            return p.error("trailing characters");
+        }
+        Ok(v)
+    }
+
+    impl Parser<'_> {
+        fn error<T>(&self, message: &str) -> Result<T> {
+            let line = self.s[..self.pos].iter().filter(|c| **c == b'\n').count() + 1;
+            Err((line, format!("json: {}", message)))
+        }
+
+        fn skip_ws(&mut self) {
+            while self.pos < self.s.len() && self.s[self.pos].is_ascii_whitespace() {
+                self.pos += 1;
+            }
+        }
+
+        fn peek(&mut self) -> Option<u8> {
+            self.skip_ws();
+            self.s.get(self.pos).cloned()
+        }
+
+        fn expect(&mut self, c: u8) -> Result<()> {
+            if self.peek() != Some(c) {
+                return self.error(&format!("expected '{}'", c as char));
+            }
+            self.pos += 1;
+            Ok(())
+        }
+
+        fn literal(&mut self, word: &str, v: Value) -> Result<Value> {
+            if !self.s[self.pos..].starts_with(word.as_bytes()) {
+                return self.error("invalid literal");
+            }
+            self.pos += word.len();
+            Ok(v)
+        }
+
+        fn value(&mut self) -> Result<Value> {
+            match self.peek() {
+                Some(b'{') => self.object(),
+                Some(b'[') => self.array(),
+                Some(b'"') => Ok(Value::Str(self.string()?)),
+                Some(b'n') => self.literal("null", Value::Null),
+                Some(b't') => self.literal("true", Value::Bool(true)),
+                Some(b'f') => self.literal("false", Value::Bool(false)),
+                Some(c) if c == b'-' || c.is_ascii_digit() => self.number(),
+                _ => self.error("expected value"),
+            }
+        }
+
+        fn object(&mut self) -> Result<Value> {
+            self.expect(b'{')?;
+            let mut m = BTreeMap::new();
+            if self.peek() == Some(b'}') {
+                self.pos += 1;
+                return Ok(Value::Object(m));
+            }
+            loop {
+                if self.peek() != Some(b'"') {
+                    return self.error("expected key");
+                }
+                let key = self.string()?;
+                self.expect(b':')?;
+                m.insert(key, self.value()?);
+                match self.peek() {
+                    Some(b',') => self.pos += 1,
+                    Some(b'}') => { self.pos += 1; return Ok(Value::Object(m)); },
+                    _ => return self.error("expected ',' or '}'"),
+                }
+            }
+        }
+
+        fn array(&mut self) -> Result<Value> {
+            self.expect(b'[')?;
+            let mut v = Vec::new();
+            if self.peek() == Some(b']') {
+                self.pos += 1;
+                return Ok(Value::Array(v));
+            }
+            loop {
+                v.push(self.value()?);
+                match self.peek() {
+                    Some(b',') => self.pos += 1,
+                    Some(b']') => { self.pos += 1; return Ok(Value::Array(v)); },
+                    _ => return self.error("expected ',' or ']'"),
+                }
+            }
+        }
+
+        fn string(&mut self) -> Result<String> {
+            self.expect(b'"')?;
+            let mut out = Vec::new();
+            loop {
+                let c = match self.s.get(self.pos) { Some(c) => *c, None => return self.error("unterminated string") };
+                self.pos += 1;
+                match c {
+                    b'"' => break,
+                    b'\\' => {
+                        let e = match self.s.get(self.pos) { Some(e) => *e, None => return self.error("unterminated string") };
+                        self.pos += 1;
+                        match e {
+                            b'"' | b'\\' | b'/' => out.push(e),
+                            b'n' => out.push(b'\n'),
+                            b't' => out.push(b'\t'),
+                            b'r' => out.push(b'\r'),
+                            b'b' => out.push(8),
+                            b'f' => out.push(12),
+                            b'u' => {
+                                let hex = self.s.get(self.pos..self.pos + 4).and_then(|h| std::str::from_utf8(h).ok()).and_then(|h| u32::from_str_radix(h, 16).ok());
+                                let c = match hex.and_then(std::char::from_u32) { Some(c) => c, None => return self.error("invalid unicode escape") };
+                                self.pos += 4;
+                                out.extend_from_slice(c.to_string().as_bytes());
+                            },
+                            _ => return self.error("invalid escape"),
+                        }
+                    },
+                    c => out.push(c),
+                }
+            }
+            match String::from_utf8(out) { Ok(s) => Ok(s), Err(_) => self.error("invalid utf-8") }
+        }
+
+        fn number(&mut self) -> Result<Value> {
+            let start = self.pos;
+            while self.pos < self.s.len() && (self.s[self.pos].is_ascii_digit() || b"+-.eE".contains(&self.s[self.pos])) {
+                self.pos += 1;
+            }
+            match std::str::from_utf8(&self.s[start..self.pos]).ok().and_then(|s| s.parse().ok()) {
+                Some(v) => Ok(Value::Num(v)),
+                None => self.error("invalid number"),
+            }
+        }
+    }
+
+    #[test]
+    fn json_test_parse() {
+        let v = parse(" {\"a\": [1, -2.5, true, null], \"b\": \"x\\\"\\u3042\", \"c\": {}} ").unwrap();
+        assert_eq!(v.get("a").unwrap().as_array().unwrap().len(), 4);
+        assert_eq!(v.get("a").unwrap().as_array().unwrap()[0].as_i64(), Some(1));
+        assert_eq!(v.get("a").unwrap().as_array().unwrap()[1].as_i64(), None);
+        assert_eq!(v.get("b").unwrap().as_str(), Some("x\"あ"));
+        assert_eq!(parse("{\"a\":\n[1,]}").unwrap_err().0, 2);
+        assert!(parse("[1] 2").is_err());
+    }
+}
+
+#[cfg(test)]
+fn record_test_game() -> GameRecord {
+    let mut packs = vec![[[1, 9], [0, 5]]; 6];
+    packs[1] = [[11, 2], [8, 0]];
+    let mut game = game::Game::new(packs.clone());
+    let mut record = GameRecord::new(packs);
+    let put = |pos, rot| action::Action::PutBlock { pos, rot };
+    record.step(&mut game, [&put(0, 0), &put(3, 1)]);
+    record.step(&mut game, [&put(0, 2), &put(8, 3)]);
+    record.step(&mut game, [&put(1, 0), &put(3, 1)]);
+    game.forfeit([false, true]);
+    record.finish(&game);
+    record
+}
+
+#[test]
+fn record_test_text() {
+    let record = record_test_game();
+    assert_eq!(record.turns.len(), 3);
+    assert_eq!(record.turns[1].turn, 1);
+    assert_eq!(record.turns[0].sides[0].board, board::Board::new());
+    assert_eq!(record.outcome, Some(game::Outcome::Win(0)));
+
+    let mut buf = Vec::new();
+    record.write_text(&mut buf).unwrap();
+    assert_eq!(GameRecord::read_text(&buf[..]).unwrap(), record);
+
+    let text = String::from_utf8(buf).unwrap();
+    let broken = text.replacen("action=0,2", "action=9,2", 1);
+    let e = GameRecord::read_text(broken.as_bytes()).unwrap_err();
+    assert_eq!(e.line, 1 + 6 + 5);
+    let truncated = text.lines().take(1 + 6 + 2).collect::<Vec<_>>().join("\n");
+    assert!(GameRecord::read_text(truncated.as_bytes()).is_err());
+}
+
+#[test]
+fn record_test_verify() {
+    let record = record_test_game();
+    assert!(record.verify().is_ok());
+
+    let mut broken = record.clone();
+    broken.turns[2].sides[1].board.put_one(9, 0);
+    let d = broken.verify().unwrap_err();
+    assert_eq!((d.turn, d.side, d.what), (2, Some(1), "board"));
+    let text = d.to_string();
+    assert!(text.starts_with("turn 2 side 1: board differs"));
+    assert_eq!(text.lines().filter(|l| l.ends_with('*')).count(), 1);
+
+    let mut broken = record.clone();
+    broken.turns[1].sides[0].result.chains = 3;
+    let d = broken.verify().unwrap_err();
+    assert_eq!((d.turn, d.side, d.what), (1, Some(0), "result"));
+
+    let mut broken = record;
+    broken.turns[1].sides[1].score += 1;
+    assert_eq!(broken.verify().unwrap_err().what, "score");
+}
+
+#[test]
+fn record_test_json() {
+    let record = record_test_game();
+    let mut buf = Vec::new();
+    record.write_json(&mut buf).unwrap();
+    let text = String::from_utf8(buf).unwrap();
+    assert_eq!(GameRecord::from_json(&text).unwrap(), record);
+
+    let mut draw = GameRecord::new(record.packs.clone());
+    draw.outcome = Some(game::Outcome::Draw);
+    let mut buf = Vec::new();
+    draw.write_json(&mut buf).unwrap();
+    assert_eq!(GameRecord::from_json(std::str::from_utf8(&buf).unwrap()).unwrap(), draw);
+
+    assert!(GameRecord::from_json(&text.replace("\"chains\"", "\"chain\"")).is_err());
+}
@@ -1,129 +1,120 @@
+use super::best_ai;
+use super::player;
+use super::rensa_plan;
+use super::replay;
+use super::strategy;
+use super::consts::W;
+use super::rand;
+
+// 一番大きい連鎖を組み、新しくお邪魔が降ってきそうになったら撃つだけのAI
+// RensaAi::with_strategy(lock, RensaStrategy::new())で作る
+pub type RensaAi<R> = best_ai::BestAi<R, RensaStrategy>;
+
+// RensaAiの手の決め方
+pub struct RensaStrategy {
+    prev_obstacle_stock: i32,
+    rand: rand::XorShiftL,
+    current_best: replay::Replay,
+}
+
+impl RensaStrategy {
+    pub fn new() -> Self {
+        Self {
+            prev_obstacle_stock: 0,
+            rand: rand::XorShiftL::new(),
+            current_best: replay::Replay::new(),
+        }
+    }
+
+    fn search(&mut self, s: &strategy::Snapshot, threatened: bool) {
+        let max_turn = if s.turn == 0 { 13 } else { 10 };
+        let mut think_time_in_milli = if s.turn == 0 { 19000 } else { 15000 };
+        if s.rest_time_in_milli < 30 * 1000 {
+            think_time_in_milli = 1000;
+        }
+        let enemy_send_obstacles = vec![0; max_turn];
+        let context = rensa_plan::PlanContext {
+            plan_start_turn: s.turn,
+            max_turn,
+            think_time_in_milli,
+            player: s.player.clone(),
+            enemy_send_obstacles: &enemy_send_obstacles,
+            packs: s.packs,
+            ..Default::default()
+        };
+        let states = rensa_plan::calc_rensa_plan(&context, &mut self.rand, |result, _, _| {
+            let obstacle_score = std::cmp::min(result.obstacle, 60);
+            obstacle_score as i64 * 100000 + result.obstacle as i64 * 16
+        });
+
+        // お邪魔が降ってくるならすぐ撃てる連鎖を選ぶ
+        let horizon = if threatened { 2 } else { states.len() };
+        if let Some(best) = Self::choose(&states[..std::cmp::min(horizon, states.len())]) {
+            self.current_best = best;
+        }
+    }
+
+    // 連鎖数が最大のもの (同じなら早く撃てるもの)
+    fn choose(states: &[replay::Replay]) -> Option<replay::Replay> {
+        let mut max = -1;
+        let mut choosed: Option<&replay::Replay> = None;
+        states.iter().filter(|s| !s.is_empty()).for_each(|s| {
+            if max < s.get_chains() as i32 {
+                max = s.get_chains() as i32;
+                choosed = Some(s);
+            }
+        });
+        choosed.cloned()
+    }
+
+    fn new_obstacle(prev_obstacle_stock: i32, player: &player::Player) -> bool {
+        let w = W as i32;
+        player.obstacle >= w && (prev_obstacle_stock - w) / w != player.obstacle / w
+    }
+}
+
+impl strategy::Strategy for RensaStrategy {
+    // 再生できる計画が無ければNoneを返してBestAiに任せる
+    fn think(&mut self, s: &strategy::Snapshot) -> Option<strategy::Decision> {
+        let threatened = Self::new_obstacle(self.prev_obstacle_stock, s.player);
+        self.prev_obstacle_stock = s.player.obstacle;
+        if !self.current_best.can_replay(s.player, &[]) || threatened {
+            self.search(s, threatened);
+        }
+
+        if self.current_best.can_replay(s.player, &[]) {
+            self.current_best.replay().map(strategy::Decision::Action)
+        } else {
+            None
+        }
+    }
+
+    fn name(&self) -> &str {
+        "test-rensa-ai"
+    }
+}
+
+impl Default for RensaStrategy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn rensa_ai_test_exec_eof() {
+    use super::protocol;
+    let mut input = Vec::new();
+    protocol::GameInput { packs: vec![[[1, 2], [3, 4]]; super::consts::MAX_TURN] }.write(&mut input).unwrap();
+
+    let summary = RensaAi::with_strategy(&input[..], RensaStrategy::new()).exec().unwrap();
+    assert_eq!(summary.turns, 0);
+    assert_eq!(summary.last_turn, None);
+
+    input.extend_from_slice(b"0\n180000\n");
+    let e = RensaAi::with_strategy(&input[..], RensaStrategy::new()).exec().unwrap_err();
+    assert!(e.error.is_eof());
+    assert_eq!(e.summary.last_turn, None);
+    // BestAiとは違う名前で名乗る
+    assert_eq!(strategy::Strategy::name(&RensaStrategy::new()), "test-rensa-ai");
+}
 
-
-// use std::str::FromStr;
-// use std::io::Read;
-// use std::io::StdinLock;
-
-// use super::action;
-// use super::board;
-// use super::player;
-// use super::rensa_plan;
-// use super::consts::{W,H,MAX_TURN};
-// use super::rand;
-
-// pub struct RensaAi<'a> {
-//     cur_turn: usize,
-//     stdin_lock: StdinLock<'a>,
-//     packs: Vec<[[u8; 2]; 2]>,
-//     prev_obstacle_stock: i32,
-//     player: player::Player,
-//     enemy: player::Player,
-//     rensa_plan: rensa_plan::RensaPlan,
-//     rand: rand::XorShiftL,
-// }
-
-// impl<'a> RensaAi<'a> {
-//     pub fn new(lock: StdinLock<'a>) -> Self {
-//         Self {
-//             cur_turn: 0,
-//             stdin_lock: lock,
-//             packs: Vec::new(),
-//             prev_obstacle_stock: 0,
-//             player: player::Player::new(board::Board::new(), 0, 0),
-//             enemy: player::Player::new(board::Board::new(), 0, 0),
-//             rensa_plan: rensa_plan::RensaPlan::new(),
-//             rand: rand::XorShiftL::new(),
-//         }
-//     }
-
-//     fn read1<T: FromStr>(&mut self) -> T {
-//         let token = self.stdin_lock.by_ref().bytes().map(|c| c.unwrap() as char)
-//             .skip_while(|c| c.is_whitespace())
-//             .take_while(|c| !c.is_whitespace())
-//             .collect::<String>();
-//         token.parse::<T>().ok().unwrap()
-//     }
-
-//     fn read_game_input(&mut self) {
-//         (0..MAX_TURN).for_each(|_| {
-//             let v1 = self.read1();
-//             let v2 = self.read1();
-//             let v3 = self.read1();
-//             let v4 = self.read1();
-//             self.read1::<String>();
-//             self.packs.push([[v1, v2], [v3, v4]]);
-//         });
-//         self.rensa_plan.set_pack(self.packs.clone());
-//     }
-
-//     fn read_board(&mut self) -> board::Board {
-//         let mut board = [0; W * H];
-//         (0..W*H).for_each(|p| { board[p as usize] = self.read1::<u8>(); });
-//         board::Board::from_board(board)
-//     }
-
-//     fn read_turn_input(&mut self) {
-//         self.prev_obstacle_stock = self.player.obstacle;
-
-//         self.cur_turn = self.read1();
-//         // eprintln!("start read {}", self.cur_turn);
-//         let _rest_time_in_milli = self.read1::<u32>();
-//         self.player.obstacle = self.read1();
-//         self.player.skill_guage = self.read1();
-//         let _player_score = self.read1::<u32>();
-//         self.player.board = self.read_board();
-//         self.read1::<String>();
-
-//         let _rest_time_in_milli = self.read1::<u32>();
-//         self.enemy.obstacle = self.read1();
-//         self.enemy.skill_guage = self.read1();
-//         let _enemy_score = self.read1::<u32>();
-//         self.enemy.board = self.read_board();
-//         self.read1::<String>();
-//     }
-
-//     pub fn exec(&mut self) {
-//         println!("test-rensa-ai");
-//         self.read_game_input();
-//         loop {
-//             self.read_turn_input();
-//             let act = self.think();
-
-//             // eprintln!("turn={}, obs={}, prev_obs={}", self.cur_turn, self.obstacle_stock, self.prev_obstacle_stock);
-//             // eprintln!("height={}", self.board.max_height());
-//             // eprintln!("{:?}", self.board);
-
-//             // self.player.put(&self.packs[self.cur_turn], &act);
-//             println!("{}", act);
-//             // assert!(self.cur_turn < 3);
-//         }
-//         // while let Some(act) = self.think() {
-//         //     println!("{}", act);
-//         // }
-//     }
-
-//     fn think(&mut self) -> action::Action {
-//         if !self.rensa_plan.can_replay(&self.player) || self.new_obstscle() {
-//         // if !self.rensa_plan.exists() {
-//             let max_turn = if self.cur_turn == 0 { 13 } else { 10 };
-//             let think_time_in_sec = if self.cur_turn == 0 { 19 } else { 15 };
-//             let context = rensa_plan::PlanContext {
-//                 plan_start_turn: self.cur_turn,
-//                 max_turn: max_turn,
-//                 think_time_in_sec,
-//                 player: self.player.clone(),
-//                 enemy_send_obstacles: vec![0; max_turn],
-//             };
-//             self.rensa_plan.calc_rensa_plan(&context, |result, player, search_turn| {
-//                 let obstacle_score = std::cmp::min(result.obstacle, 60);
-//                 (obstacle_score * 100000 - search_turn as i32 * 1000 + result.obstacle * 16 + (self.rand.next() & 0xF) as i32)
-//             });
-//         }
-//         self.rensa_plan.replay()
-//     }
-
-//     fn new_obstscle(&self) -> bool {
-//         let w = W as i32;
-//         self.player.obstacle >= w && (self.prev_obstacle_stock - w) / w != self.player.obstacle / w
-//     }
-// }
@@ -5,49 +5,57 @@ use super::player;
 // use super::rand;
 use super::rand;
 use super::replay;
+use super::transposition;
+use super::node_pool;
 
 use std::collections::BinaryHeap;
 use std::cmp::Ordering;
+use std::sync::{Barrier, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
 use std::time::{Instant};
 
 use super::consts::*;
-use std::collections::HashSet;
-// use hashbrown::HashSet;
 
 // 探索結果
-#[derive(Clone, Default, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq)]
 struct SearchResult {
     score: i64,
-    actions: u128,
+    actions: action::ActionSeq,
 }
 
-impl SearchResult {
-    fn get_actions(&self) -> Vec<action::Action> {
-        let b = (128 - self.actions.leading_zeros() + 7) / 8;
-        let mut a = self.actions;
-        let mut res = Vec::with_capacity(b as usize);
-        while a != 0 {
-            res.push(((a & 0xFF) as u8).into());
-            a >>= 8;
-        }
-        res
+// 評価値が負の計画 (Expectationで死ぬ想定がある計画など) も残せるように、空の結果は一番低い値にする
+impl Default for SearchResult {
+    fn default() -> Self {
+        Self { score: i64::MIN, actions: Default::default() }
     }
 }
 
-// ビームサーチ状態
+// ビームサーチの状態の本体 (NodePoolに置く)
+// playerは1つ目の想定、othersは2つ目以降の想定でのプレイヤー
 #[derive(Clone, Default, PartialEq, Eq)]
-struct BeamState {
+struct Node {
     player: player::Player,
-    score: i64,
-    actions: u128,
+    others: Vec<player::Player>,
+    actions: action::ActionSeq,
 }
 
-impl BeamState {
-    fn new(player: player::Player, score: i64, actions: u128) -> Self {
-        Self { player, score, actions, }
+impl Node {
+    fn players(&self) -> impl Iterator<Item = &player::Player> {
+        std::iter::once(&self.player).chain(self.others.iter())
+    }
+
+    fn hash(&self) -> u64 {
+        self.others.iter().enumerate().fold(self.player.hash(), |h, (i, p)| h ^ rand::splitmix64(p.hash().wrapping_add(i as u64)))
     }
 }
 
+// ヒープに積む状態
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct BeamState {
+    score: i64,
+    node: node_pool::NodeId,
+}
+
 impl Ord for BeamState {
     fn cmp(&self, other: &Self) -> Ordering {
         self.score.cmp(&other.score)
@@ -60,12 +68,6 @@ impl PartialOrd for BeamState {
     }
 }
 
-fn push_action(actions: u128, a: &action::Action) -> u128 {
-    let b = (128 - actions.leading_zeros() + 7) / 8;
-    let a: u128 = a.into();
-    actions | a << (b * 8)
-}
-
 pub struct PlanContext<'a> {
     pub plan_start_turn: usize,
     pub max_turn: usize,
@@ -73,103 +75,642 @@ pub struct PlanContext<'a> {
     pub player: player::Player,
     pub enemy_send_obstacles: &'a [i32],
     pub packs: &'a [[[u8; 2]; 2]],
+    // 探索するスレッド数 (1なら呼び出し元のスレッドだけで探索する)
+    pub num_threads: usize,
+    // どちらかを指定すると時間ではなく展開したノード数か反復回数で打ち切る
+    pub max_nodes: Option<usize>,
+    pub max_iterations: Option<usize>,
+    // 指定すると渡された乱数ではなくこのシードを使う
+    // max_nodesかmax_iterationsと組み合わせ、num_threads=1なら同じ入力で常に同じ結果になる
+    pub seed: Option<u64>,
+    // 置換表の要素数
+    pub table_capacity: usize,
+    pub mode: SearchMode,
+    // 深さごとに展開する状態数の上限 (足りない分は最後の値、空なら無制限)
+    pub beam_widths: &'a [usize],
+    // Chokudaiで1反復に各深さから取り出す状態数
+    pub pops_per_iteration: usize,
+    // 各深さのヒープに残す状態数の上限 (0なら無制限)。2倍を超えたら上位だけ残す
+    pub heap_capacity: usize,
+    // 降ってくるお邪魔の想定。空ならenemy_send_obstaclesだけを想定する
+    // 指定するときはenemy_send_obstaclesを空にするかscenarios[0].obstaclesと同じにする (違えばpanic)
+    // 評価値はaggregationでまとめ、返す計画は1つ目の想定で再生したもの
+    // 1つ目の想定で死ぬ手は選ばない。2つ目以降で死ぬ手はWorstCaseなら選ばず、Expectationならその想定をDEATH_SCOREとして数える
+    pub scenarios: &'a [ObstacleScenario<'a>],
+    pub aggregation: Aggregation,
+}
+
+// 相手から送られてくるお邪魔の想定の1つ (obstacles[i]はi手目の後に届く数)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ObstacleScenario<'a> {
+    pub weight: u32,
+    pub obstacles: &'a [i32],
+}
+
+// Expectationで死んだ想定の評価値。重みを掛けて平均するので、重みの小さい想定で死ぬ手ほど損が小さい
+pub const DEATH_SCORE: i64 = -(1 << 40);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aggregation {
+    // 一番悪い想定の評価値
+    WorstCase,
+    // 重み付き平均
+    Expectation,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchMode {
+    // 時間いっぱい全部の深さから少しずつ取り出して展開し続ける
+    Chokudai,
+    // 深さ順に上位beam_widths個ずつ展開して1回で終わる
+    Beam,
+}
+
+impl<'a> PlanContext<'a> {
+    fn scenarios(&self) -> Vec<ObstacleScenario<'a>> {
+        if self.scenarios.is_empty() {
+            vec![ObstacleScenario { weight: 1, obstacles: self.enemy_send_obstacles }]
+        } else {
+            self.scenarios.to_vec()
+        }
+    }
+
+    fn beam_width(&self, search_turn: usize) -> usize {
+        match self.beam_widths.last() {
+            None => usize::MAX,
+            Some(last) => *self.beam_widths.get(search_turn).unwrap_or(last),
+        }
+    }
+}
+
+impl Default for PlanContext<'_> {
+    fn default() -> Self {
+        Self {
+            plan_start_turn: 0,
+            max_turn: 0,
+            think_time_in_milli: 0,
+            player: Default::default(),
+            enemy_send_obstacles: &[],
+            packs: &[],
+            num_threads: 1,
+            max_nodes: None,
+            max_iterations: None,
+            seed: None,
+            table_capacity: transposition::DEFAULT_CAPACITY,
+            mode: SearchMode::Chokudai,
+            beam_widths: &[],
+            pops_per_iteration: 1,
+            heap_capacity: 0,
+            scenarios: &[],
+            aggregation: Aggregation::WorstCase,
+        }
+    }
+}
+
+pub fn available_threads() -> usize {
+    std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
 }
 
 // 一手進める
-fn do_action<F>(player: &mut player::Player, search_turn: usize, context: &PlanContext, action: &action::Action, calc_score: &F) -> (i64, i64)
+fn do_action<F>(player: &mut player::Player, search_turn: usize, context: &PlanContext, enemy_send_obstacles: &[i32], action: &action::Action, calc_score: &F) -> (i64, i64)
     where F: Fn(&action::ActionResult, &player::Player, &board::Feature) -> i64 + Sync + Send
 {
     let turn = context.plan_start_turn + search_turn;
     let pack = &context.packs[turn];
     let result = player.put(pack, action);
 
-    if search_turn < context.enemy_send_obstacles.len() {
-        player.add_obstacles(context.enemy_send_obstacles[search_turn]);
+    if search_turn < enemy_send_obstacles.len() {
+        player.add_obstacles(enemy_send_obstacles[search_turn]);
     }
 
-    let feature = player.board.calc_feature();
     let eval_result = player.board.calc_max_rensa_by_erase_block().1;
+    let feature = player.board.calc_feature_with_potential(&eval_result);
     let score = calc_score(&result, player, &feature);
     let eval_score = calc_score(&eval_result, player, &feature);
     (score, eval_score)
 }
 
-// ビームサーチ
-pub fn calc_rensa_plan<F>(context: &PlanContext, rand: &mut rand::XorShiftL, calc_score: F) -> Vec<replay::Replay>
-    where F: Fn(&action::ActionResult, &player::Player, &board::Feature) -> i64 + Sync + Send
-{
-    assert!(context.max_turn <= 16);
-    let timer = Instant::now();
+// 探索の統計
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    pub nodes: usize,
+    pub iterations: usize,
+    pub table: transposition::TableStats,
+    pub pool: node_pool::PoolStats,
+}
 
-    let actions = action::Action::all_actions();
-    let mut heaps = vec![BinaryHeap::new(); context.max_turn];
+// スレッドごとに最初から確保しておく状態数
+const NODES_PER_THREAD: usize = 1 << 16;
 
-    let mut bests: Vec<SearchResult> = vec![Default::default(); context.max_turn];
-    let initial_state = BeamState::new(context.player.clone(), 0, 0);
-    heaps[0].push(initial_state);
+// 1つの深さの展開待ちの状態
+#[derive(Default)]
+struct DepthQueue {
+    heap: BinaryHeap<BeamState>,
+    // これまでに取り出した数
+    popped: usize,
+}
 
-    let mut visited = HashSet::new();
-    visited.insert(context.player.hash());
+impl DepthQueue {
+    // 上限を超えて捨てた状態を返す
+    fn extend(&mut self, states: impl Iterator<Item = BeamState>, capacity: usize) -> Vec<BeamState> {
+        self.heap.extend(states);
+        if capacity > 0 && self.heap.len() > capacity * 2 {
+            let mut states = std::mem::take(&mut self.heap).into_sorted_vec();
+            let drop = states.len() - capacity;
+            self.heap = states.drain(drop..).collect();
+            return states;
+        }
+        Vec::new()
+    }
+}
+
+// 全スレッドで共有する探索状態
+struct SharedSearch<'a, 'b, F> {
+    context: &'a PlanContext<'b>,
+    calc_score: &'a F,
+    actions: Vec<action::Action>,
+    queues: Vec<Mutex<DepthQueue>>,
+    pool: node_pool::NodePool<Node>,
+    scenarios: Vec<ObstacleScenario<'b>>,
+    bests: Vec<Mutex<SearchResult>>,
+    table: transposition::TranspositionTable,
+    // 展開中の状態数。全ヒープが空でもこれが0でなければ他のスレッドが状態を積む可能性がある
+    expanding: AtomicUsize,
+    nodes: AtomicUsize,
+    iterations: AtomicUsize,
+    board_is_empty: bool,
+    timer: Instant,
+    // Beamで深さを揃えるため
+    barrier: Barrier,
+}
 
-    let board_is_empty = context.player.board.is_empty();
-    let mut _iter = 0;
-    loop {
-        let elapsed = timer.elapsed();
-        let milli = elapsed.as_secs() * 1000 + (elapsed.subsec_nanos() / 1000_000) as u64;
-        if milli >= context.think_time_in_milli || heaps.iter().all(|h| h.is_empty()) {
-            break;
+impl<'a, 'b, F> SharedSearch<'a, 'b, F>
+    where F: Fn(&action::ActionResult, &player::Player, &board::Feature) -> i64 + Sync + Send
+{
+    fn is_over_budget(&self) -> bool {
+        let context = self.context;
+        if context.max_nodes.is_some() || context.max_iterations.is_some() {
+            let nodes = self.nodes.load(AtomicOrdering::SeqCst);
+            let iterations = self.iterations.load(AtomicOrdering::SeqCst);
+            return context.max_nodes.is_some_and(|n| nodes >= n) || context.max_iterations.is_some_and(|n| iterations >= n);
+        }
+        let elapsed = self.timer.elapsed();
+        let milli = elapsed.as_secs() * 1000 + elapsed.subsec_millis() as u64;
+        milli >= context.think_time_in_milli
+    }
+
+    fn is_finished(&self) -> bool {
+        self.is_over_budget()
+            || self.expanding.load(AtomicOrdering::SeqCst) == 0 && self.queues.iter().enumerate().all(|(search_turn, q)| {
+                let q = q.lock().unwrap();
+                q.heap.is_empty() || q.popped >= self.context.beam_width(search_turn)
+            })
+    }
+
+    // 幅を使い切っていれば取り出さない
+    fn pop(&self, search_turn: usize) -> Option<BeamState> {
+        self.expanding.fetch_add(1, AtomicOrdering::SeqCst);
+        let mut q = self.queues[search_turn].lock().unwrap();
+        let b = if q.popped < self.context.beam_width(search_turn) { q.heap.pop() } else { None };
+        match b {
+            Some(_) => q.popped += 1,
+            None => { self.expanding.fetch_sub(1, AtomicOrdering::SeqCst); },
         }
+        b
+    }
 
-        _iter += 1;
+    // 想定ごとの(score, eval_score)をまとめる
+    fn aggregate(&self, scores: &[(i64, i64)]) -> (i64, i64) {
+        match self.context.aggregation {
+            Aggregation::WorstCase => (scores.iter().map(|s| s.0).min().unwrap(), scores.iter().map(|s| s.1).min().unwrap()),
+            Aggregation::Expectation => {
+                let total = self.scenarios.iter().map(|s| s.weight as i128).sum::<i128>().max(1);
+                let mean = |f: fn(&(i64, i64)) -> i64| {
+                    (scores.iter().zip(self.scenarios.iter()).map(|(v, s)| f(v) as i128 * s.weight as i128).sum::<i128>() / total) as i64
+                };
+                (mean(|v| v.0), mean(|v| v.1))
+            },
+        }
+    }
 
-        (0..context.max_turn).for_each(|search_turn| {
-            let turn = context.plan_start_turn + search_turn;
+    // popした状態を全部の手で1手進めて次の深さに積む
+    fn expand(&self, worker: usize, search_turn: usize, b: BeamState, rand: &mut rand::XorShiftL, next: &mut Vec<BeamState>) {
+        let context = self.context;
+        let b = self.pool.take(b.node);
+        let turn = context.plan_start_turn + search_turn;
+        let mut best = SearchResult::default();
+        self.actions.iter().for_each(|a| {
+            if &action::Action::UseSkill == a && !b.players().filter(|p| !p.board.is_dead()).all(|p| p.can_use_skill()) {
+                return;
+            }
 
-            if let Some(b) = heaps[search_turn].pop() {
-                actions.iter().for_each(|a| {
-                    if &action::Action::UseSkill == a && !b.player.can_use_skill() {
+            if self.board_is_empty && turn == context.plan_start_turn {
+                if let action::Action::PutBlock { pos, rot: _ } = a {
+                    if *pos != W / 2 {
                         return;
                     }
+                }
+            }
 
-                    if board_is_empty && turn == context.plan_start_turn {
-                        if let action::Action::PutBlock { pos, rot: _ } = a {
-                            if *pos != W / 2 {
-                                return;
-                            }
+            let mut node = Node { player: b.player.clone(), others: b.others.clone(), actions: b.actions.pushed(a) };
+            let (score, eval_score) = do_action(&mut node.player, search_turn, context, self.scenarios[0].obstacles, a, self.calc_score);
+            self.nodes.fetch_add(1, AtomicOrdering::SeqCst);
+            if node.player.board.is_dead() {
+                return;
+            }
+            let (score, eval_score) = if node.others.is_empty() {
+                (score, eval_score)
+            } else {
+                let mut scores = vec![(score, eval_score)];
+                for (p, scenario) in node.others.iter_mut().zip(self.scenarios[1..].iter()) {
+                    // 死んだ想定はそれ以上進めない
+                    if p.board.is_dead() {
+                        scores.push((DEATH_SCORE, DEATH_SCORE));
+                        continue;
+                    }
+                    scores.push(do_action(p, search_turn, context, scenario.obstacles, a, self.calc_score));
+                    if p.board.is_dead() {
+                        if context.aggregation == Aggregation::WorstCase {
+                            return;
                         }
+                        *scores.last_mut().unwrap() = (DEATH_SCORE, DEATH_SCORE);
                     }
+                }
+                self.aggregate(&scores)
+            };
+            let actions = node.actions;
 
-                    let mut player = b.player.clone();
-                    let (score, eval_score) = do_action(&mut player, search_turn, context, a, &calc_score);
-                    let actions = push_action(b.actions, a);
-                    
-                    // if player.board.is_dead() || !context.enemy_send_obstacles.is_empty() && !visited.insert(player.hash()) {
-                    if player.board.is_dead() || !visited.insert(player.hash()) {
-                    // if player.board.is_dead() {
-                        return;
-                    }
-                    let score = score * 256 + (rand.next() & 0xFF) as i64;
-                    let eval_score = eval_score * 256 + (rand.next() & 0xFF) as i64;
-                    if search_turn + 1 < context.max_turn {
-                        heaps[search_turn + 1].push(BeamState::new(player.clone(), eval_score, actions));
+            // 同じ状態に前より浅いか良い評価値で来たときだけ続ける
+            // 乱数を混ぜると同じ評価値でも通ってしまうので、表には混ぜる前の値を入れる
+            if !self.table.insert(node.hash(), search_turn + 1, eval_score) {
+                return;
+            }
+            let score = score * 256 + (rand.next() & 0xFF) as i64;
+            if search_turn + 1 < context.max_turn {
+                let node = self.pool.alloc(worker, node);
+                next.push(BeamState { score: eval_score * 256 + (rand.next() & 0xFF) as i64, node });
+            }
+            if best.score < score {
+                best = SearchResult { score, actions, };
+            }
+        });
+
+        if !next.is_empty() {
+            let dropped = self.queues[search_turn + 1].lock().unwrap().extend(next.drain(..), context.heap_capacity);
+            dropped.iter().for_each(|b| self.pool.free(b.node));
+        }
+        let mut bests = self.bests[search_turn].lock().unwrap();
+        if bests.score < best.score {
+            *bests = best;
+        }
+        drop(bests);
+        self.expanding.fetch_sub(1, AtomicOrdering::SeqCst);
+    }
+
+    // workerはスレッドの番号
+    fn run(&self, worker: usize, rand: &mut rand::XorShiftL) {
+        match self.context.mode {
+            SearchMode::Chokudai => self.run_chokudai(worker, rand),
+            SearchMode::Beam => self.run_beam(worker, rand),
+        }
+    }
+
+    fn run_chokudai(&self, worker: usize, rand: &mut rand::XorShiftL) {
+        let context = self.context;
+        let mut next = Vec::new();
+        while !self.is_finished() {
+            self.iterations.fetch_add(1, AtomicOrdering::SeqCst);
+
+            for search_turn in 0..context.max_turn {
+                for _ in 0..context.pops_per_iteration {
+                    if self.is_over_budget() {
+                        break;
                     }
-                    if bests[search_turn].score < score {
-                        bests[search_turn] = SearchResult { score, actions, };
+                    match self.pop(search_turn) {
+                        Some(b) => self.expand(worker, search_turn, b, rand, &mut next),
+                        None => break,
                     }
-                });
-            };
+                }
+            }
+        }
+        // eprintln!("iter={}", self.iterations.load(AtomicOrdering::SeqCst));
+    }
+
+    // 全スレッドがある深さを展開し終えてから次の深さに進む
+    fn run_beam(&self, worker: usize, rand: &mut rand::XorShiftL) {
+        let context = self.context;
+        let mut next = Vec::new();
+        self.iterations.store(1, AtomicOrdering::SeqCst);
+        for search_turn in 0..context.max_turn {
+            while !self.is_over_budget() {
+                match self.pop(search_turn) {
+                    Some(b) => self.expand(worker, search_turn, b, rand, &mut next),
+                    None => break,
+                }
+            }
+            self.barrier.wait();
+        }
+    }
+}
+
+// ビームサーチ
+// 深さごとのヒープをnum_threads個のスレッドで取り合って展開する
+pub fn calc_rensa_plan<F>(context: &PlanContext, rand: &mut rand::XorShiftL, calc_score: F) -> Vec<replay::Replay>
+    where F: Fn(&action::ActionResult, &player::Player, &board::Feature) -> i64 + Sync + Send
+{
+    calc_rensa_plan_with_stats(context, rand, calc_score).0
+}
+
+pub fn calc_rensa_plan_with_stats<F>(context: &PlanContext, rand: &mut rand::XorShiftL, calc_score: F) -> (Vec<replay::Replay>, SearchStats)
+    where F: Fn(&action::ActionResult, &player::Player, &board::Feature) -> i64 + Sync + Send
+{
+    assert!(context.max_turn <= action::MAX_ACTION_SEQ_LEN);
+    assert!(context.pops_per_iteration > 0);
+    assert!(context.scenarios.is_empty() || context.enemy_send_obstacles.is_empty() || context.enemy_send_obstacles == context.scenarios[0].obstacles,
+        "enemy_send_obstacles must be empty or match scenarios[0] when scenarios are given");
+
+    let num_threads = std::cmp::max(context.num_threads, 1);
+    let search = SharedSearch {
+        context,
+        calc_score: &calc_score,
+        actions: action::Action::all_actions(),
+        queues: (0..context.max_turn).map(|_| Mutex::new(Default::default())).collect(),
+        // ノード数で打ち切るならそれ以上は確保しない
+        pool: node_pool::NodePool::new(num_threads, context.max_nodes.map_or(NODES_PER_THREAD, |n| std::cmp::min(n + 1, NODES_PER_THREAD))),
+        scenarios: context.scenarios(),
+        bests: (0..context.max_turn).map(|_| Mutex::new(Default::default())).collect(),
+        table: transposition::TranspositionTable::new(context.table_capacity),
+        expanding: AtomicUsize::new(0),
+        nodes: AtomicUsize::new(0),
+        iterations: AtomicUsize::new(0),
+        board_is_empty: context.player.board.is_empty(),
+        timer: Instant::now(),
+        barrier: Barrier::new(num_threads),
+    };
+    if context.max_turn > 0 {
+        let others = vec![context.player.clone(); search.scenarios.len() - 1];
+        let node = search.pool.alloc(0, Node { player: context.player.clone(), others, actions: Default::default() });
+        search.queues[0].lock().unwrap().heap.push(BeamState { score: 0, node });
+    }
+    let root = Node { player: context.player.clone(), others: vec![context.player.clone(); search.scenarios.len() - 1], actions: Default::default() };
+    search.table.insert(root.hash(), 0, i64::MAX);
+
+    let mut seeded = context.seed.map(rand::XorShiftL::from_seed);
+    let rand = seeded.as_mut().unwrap_or(rand);
+    if num_threads == 1 {
+        search.run(0, rand);
+    } else {
+        // 各スレッドは重ならない乱数列を使う
+        let base = rand::XorShiftL::from_seed(rand.next());
+        let mut rands = (0..num_threads).map(|i| base.stream(i)).collect::<Vec<_>>();
+        std::thread::scope(|scope| {
+            rands.iter_mut().enumerate().for_each(|(worker, rand)| {
+                let search = &search;
+                scope.spawn(move || search.run(worker, rand));
+            });
         });
     }
 
-    // eprintln!("iter={}", _iter);
+    let stats = SearchStats {
+        nodes: search.nodes.load(AtomicOrdering::SeqCst),
+        iterations: search.iterations.load(AtomicOrdering::SeqCst),
+        table: search.table.stats(),
+        pool: search.pool.stats(),
+    };
     // bests.iter().for_each(|b| { eprintln!("obstacle={}", b.0.score / 10000000000); });
-    bests.into_iter().map(|b| {
+    let obstacles = search.scenarios[0].obstacles;
+    let plans = search.bests.into_iter().map(|b| {
+        let b = b.into_inner().unwrap();
         let mut replay = replay::Replay::new();
-        let actions = b.get_actions();
+        let actions = b.actions.to_vec();
         let start_turn = context.plan_start_turn;
         let last_turn = start_turn + actions.len();
-        replay.init(&context.player, &context.packs[start_turn..last_turn], context.enemy_send_obstacles, &actions);
+        replay.init(&context.player, &context.packs[start_turn..last_turn], obstacles, &actions);
         replay
+    }).collect();
+    (plans, stats)
+}
+
+#[cfg(test)]
+fn rensa_plan_test_packs() -> Vec<[[u8; 2]; 2]> {
+    let mut rand = rand::XorShiftL::from_seed(1);
+    (0..MAX_TURN).map(|_| {
+        let mut pack = [[0; 2]; 2];
+        pack.iter_mut().flatten().for_each(|v| *v = rand.gen_range(1..10) as u8);
+        pack
     }).collect()
 }
 
+#[test]
+fn rensa_plan_test_parallel() {
+    let packs = rensa_plan_test_packs();
+    let context = PlanContext {
+        max_turn: 6,
+        think_time_in_milli: 300,
+        packs: &packs,
+        num_threads: 4,
+        ..Default::default()
+    };
+    let plans = calc_rensa_plan(&context, &mut rand::XorShiftL::new(), |result, _, feature| {
+        result.obstacle as i64 * 1000 + feature.num_block as i64
+    });
+    assert_eq!(plans.len(), 6);
+    plans.iter().enumerate().filter(|(_, p)| !p.is_empty()).for_each(|(i, p)| {
+        assert_eq!(p.len(), i + 1);
+        assert!(p.can_replay(&context.player, context.enemy_send_obstacles));
+    });
+    assert!(!plans[5].is_empty());
+}
+
+#[test]
+fn rensa_plan_test_deterministic() {
+    let packs = rensa_plan_test_packs();
+    let plan = |rand: &mut rand::XorShiftL, max_nodes, max_iterations| {
+        let context = PlanContext {
+            max_turn: 5,
+            think_time_in_milli: 0,
+            packs: &packs,
+            max_nodes,
+            max_iterations,
+            seed: Some(12345),
+            ..Default::default()
+        };
+        let plans = calc_rensa_plan(&context, rand, |result, _, feature| {
+            result.obstacle as i64 * 1000 + feature.keima as i64 * 10 + feature.num_block as i64
+        });
+        plans.iter().map(|p| p.get_actions()).collect::<Vec<_>>()
+    };
+
+    let a = plan(&mut rand::XorShiftL::new(), Some(2000), None);
+    let b = plan(&mut rand::XorShiftL::from_seed(7), Some(2000), None);
+    assert_eq!(a, b);
+    assert!(!a[4].is_empty());
+
+    let a = plan(&mut rand::XorShiftL::new(), None, Some(20));
+    let b = plan(&mut rand::XorShiftL::from_seed(7), None, Some(20));
+    assert_eq!(a, b);
+}
+
+#[test]
+fn rensa_plan_test_stats() {
+    let packs = rensa_plan_test_packs();
+    let context = PlanContext {
+        max_turn: 4,
+        packs: &packs,
+        max_nodes: Some(3000),
+        seed: Some(1),
+        ..Default::default()
+    };
+    let (plans, stats) = calc_rensa_plan_with_stats(&context, &mut rand::XorShiftL::new(), |result, _, feature| {
+        result.obstacle as i64 * 1000 + feature.num_block as i64
+    });
+    assert!(!plans[3].is_empty());
+    assert!(stats.nodes >= 3000 && stats.iterations > 0);
+    // 引いた状態は捨てるか書き込むかのどちらか
+    let table = stats.table;
+    assert_eq!(table.probes, table.hits + table.updates + table.stores);
+    assert!(table.hits > 0);
+    assert!(table.probes <= stats.nodes + 1);
+    // 取り出した状態のスロットは使い回される
+    let pool = stats.pool;
+    assert!(pool.reused > 0 && pool.slots < pool.allocs);
+    assert_eq!(pool.allocs, pool.reused + pool.slots);
+}
+
+#[test]
+fn rensa_plan_test_transposition() {
+    // どの向きに置いても同じ盤面になるパック
+    let packs = vec![[[1, 1], [1, 1]]; 2];
+    let context = PlanContext {
+        max_turn: 1,
+        packs: &packs,
+        num_threads: 1,
+        max_nodes: Some(1000),
+        seed: Some(1),
+        ..Default::default()
+    };
+    let (_, stats) = calc_rensa_plan_with_stats(&context, &mut rand::XorShiftL::new(), |_, _, _| 0);
+    // 空の盤面では真ん中にしか置かないので、根と4つの向きのうち最初の1つだけが書き込まれる
+    assert_eq!((stats.table.probes, stats.table.stores, stats.table.updates, stats.table.hits), (5, 2, 0, 3));
+}
+
+#[test]
+fn rensa_plan_test_beam_width() {
+    let packs = rensa_plan_test_packs();
+    let calc_score = |result: &action::ActionResult, _: &player::Player, feature: &board::Feature| {
+        result.obstacle as i64 * 1000 + feature.num_block as i64
+    };
+    let actions = action::Action::all_actions().len();
+    let timer = Instant::now();
+    for (mode, num_threads) in [(SearchMode::Beam, 1), (SearchMode::Beam, 3), (SearchMode::Chokudai, 2)] {
+        // 幅を使い切ったら時間が残っていても終わる
+        let context = PlanContext {
+            max_turn: 4,
+            think_time_in_milli: 60 * 1000,
+            packs: &packs,
+            num_threads,
+            mode,
+            beam_widths: &[1, 3, 5],
+            pops_per_iteration: 2,
+            ..Default::default()
+        };
+        let (plans, stats) = calc_rensa_plan_with_stats(&context, &mut rand::XorShiftL::from_seed(1), calc_score);
+        assert!(plans.iter().all(|p| !p.is_empty()), "{:?}", mode);
+        assert!(stats.nodes <= (1 + 3 + 5 + 5) * actions);
+    }
+    assert!(timer.elapsed().as_secs() < 30);
+}
+
+#[test]
+fn rensa_plan_test_heap_capacity() {
+    let pool = node_pool::NodePool::<Node>::new(1, 8);
+    let mut q = DepthQueue::default();
+    let state = |score| BeamState { score, node: pool.alloc(0, Default::default()) };
+    assert!(q.extend((0..6).map(state), 3).is_empty());
+    assert_eq!(q.heap.len(), 6);
+    let dropped = q.extend((6..8).map(state), 3);
+    assert_eq!(dropped.iter().map(|b| b.score).collect::<Vec<_>>(), vec![0, 1, 2, 3, 4]);
+    assert_eq!(q.heap.into_sorted_vec().iter().map(|b| b.score).collect::<Vec<_>>(), vec![5, 6, 7]);
+}
+
+#[test]
+fn rensa_plan_test_long_horizon() {
+    let packs = rensa_plan_test_packs();
+    let context = PlanContext {
+        max_turn: 24,
+        think_time_in_milli: 60 * 1000,
+        packs: &packs,
+        mode: SearchMode::Beam,
+        beam_widths: &[2],
+        ..Default::default()
+    };
+    let plans = calc_rensa_plan(&context, &mut rand::XorShiftL::from_seed(1), |result, _, feature| {
+        result.obstacle as i64 * 1000 + feature.num_block as i64
+    });
+    assert_eq!(plans.len(), 24);
+    assert_eq!(plans[23].len(), 24);
+    assert!(plans[23].can_replay(&context.player, context.enemy_send_obstacles));
+}
+
+#[test]
+fn rensa_plan_test_scenarios() {
+    let packs = rensa_plan_test_packs();
+    // 高く積まれていて、お邪魔が降ると死にやすい盤面
+    let board = ["XXXXXXXXXX"; 9].join("/").parse::<board::Board>().unwrap();
+    let late = [0, 0, 120];
+    let scenarios = [
+        ObstacleScenario { weight: 3, obstacles: &[] },
+        ObstacleScenario { weight: 1, obstacles: &[120] },
+        ObstacleScenario { weight: 1, obstacles: &late },
+    ];
+    for aggregation in [Aggregation::WorstCase, Aggregation::Expectation] {
+        let context = PlanContext {
+            max_turn: 4,
+            packs: &packs,
+            max_nodes: Some(3000),
+            seed: Some(1),
+            player: player::Player::new(board.clone(), 0, 0),
+            scenarios: &scenarios,
+            aggregation,
+            ..Default::default()
+        };
+        let plans = calc_rensa_plan(&context, &mut rand::XorShiftL::new(), |result, _, feature| {
+            result.obstacle as i64 * 1000 + feature.num_block as i64
+        });
+        assert!(!plans[3].is_empty(), "{:?}", aggregation);
+        // どの想定で進めても死なない
+        plans.iter().filter(|p| !p.is_empty()).for_each(|p| {
+            scenarios.iter().for_each(|s| {
+                let mut player = context.player.clone();
+                p.get_actions().iter().enumerate().for_each(|(turn, a)| {
+                    do_action(&mut player, turn, &context, s.obstacles, a, &|_: &action::ActionResult, _: &player::Player, _: &board::Feature| 0);
+                });
+                assert!(!player.board.is_dead(), "{:?}", aggregation);
+            });
+        });
+    }
+
+    // どの手でも3手目で死ぬ想定があるとき、WorstCaseでは3手の計画を選ばず、Expectationでは他の想定で組む
+    let board = ["XXXXXXXXXX"; 14].join("/").parse::<board::Board>().unwrap();
+    let doomed = [
+        ObstacleScenario { weight: 3, obstacles: &[] },
+        ObstacleScenario { weight: 1, obstacles: &[200] },
+    ];
+    let plan = |aggregation| {
+        let context = PlanContext {
+            max_turn: 3,
+            packs: &packs,
+            max_nodes: Some(1000),
+            seed: Some(1),
+            player: player::Player::new(board.clone(), 0, 0),
+            scenarios: &doomed,
+            aggregation,
+            ..Default::default()
+        };
+        calc_rensa_plan(&context, &mut rand::XorShiftL::new(), |result, _, feature| {
+            result.obstacle as i64 * 1000 + feature.num_block as i64
+        })
+    };
+    assert!(plan(Aggregation::WorstCase)[2].is_empty());
+    assert!(!plan(Aggregation::Expectation)[2].is_empty());
+}
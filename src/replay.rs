@@ -41,7 +41,7 @@ impl Replay {
             if a == &action::Action::UseSkill && !p.can_use_skill() {
                 illegal_action = true;
             }
-            let result = p.put(pack, &a);
+            let result = p.put(pack, a);
             if turn < enemy_send_obstacles.len() {
                 p.add_obstacles(enemy_send_obstacles[turn]);
             }
@@ -59,7 +59,7 @@ impl Replay {
         let mut p = player.clone();
         let mut turn = 0;
         self.expected_results = self.actions.iter().zip(self.packs.iter()).map(|(a, pack)| {
-            let result = p.put(pack, &a);
+            let result = p.put(pack, a);
             if turn < enemy_send_obstacles.len() {
                 p.add_obstacles(enemy_send_obstacles[turn]);
             }
@@ -71,13 +71,17 @@ impl Replay {
     pub fn replay(&mut self) -> Option<action::Action> {
         let pack = self.packs.pop_front();
         self.expected_results.pop_front();
-        let a = self.actions.pop_front().map(|a| a.into());
+        let a = self.actions.pop_front();
         if a.is_some() {
             self.player.put(&pack.unwrap(), &a.clone().unwrap());
         }
         a
     }
 
+    pub fn peek(&self) -> Option<&action::Action> {
+        self.actions.front()
+    }
+
     pub fn clear(&mut self) {
         self.packs.clear();
         self.actions.clear();
@@ -129,3 +133,9 @@ impl Replay {
         self.actions.len()
     }
 }
+
+impl Default for Replay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -8,6 +8,8 @@ thread_local! {
 const MAX: usize = 200;
 
 pub struct ScoreCalculator {
+    bomb_score: Vec<i32>,       // floor(25*2^(b/12))
+    chain_score: Vec<i32>,      // sum(i=1,c,floor(1.3^i))
     bomb_obstacle: Vec<i32>,    // floor(floor(25*2^(b/12))/2)
     chian_obstacle: Vec<i32>,   // floor(sum(i=1,c,floor(1.3^i))/2)
     skill_guage: Vec<i32>,      // sum(i=1,c,floor(1.3^i))
@@ -17,6 +19,8 @@ impl ScoreCalculator {
     fn new() -> Self {
         let mut pow13 = vec![1_f64; MAX + 1];
         let mut pow12 = vec![1_f64; MAX + 1];
+        let mut bomb_score = vec![0; MAX + 1];
+        let mut chain_score = vec![0; MAX + 1];
         let mut bomb_obstacle = vec![0; MAX + 1];
         let mut chian_obstacle = vec![0; MAX + 1];
         let mut skill_guage = vec![0; MAX + 1];
@@ -26,8 +30,10 @@ impl ScoreCalculator {
             pow13[i+1] = pow13[i] * 1.3;
             pow12[i+1] = pow12[i] * inv_12;
             sum += pow13[i+1].floor();
-            let bomb_score = (25.0 * pow12[i+1]).floor();
-            bomb_obstacle[i+1] = (bomb_score / 2.0).floor() as i32;
+            let score = (25.0 * pow12[i+1]).floor();
+            bomb_score[i+1] = score as i32;
+            chain_score[i+1] = sum as i32;
+            bomb_obstacle[i+1] = (score / 2.0).floor() as i32;
             chian_obstacle[i+1] = (sum / 2.0).floor() as i32;
             if i+1 >= 3 {
                 skill_guage[i+1] = 12 + 2 * (i+1) as i32;
@@ -35,6 +41,8 @@ impl ScoreCalculator {
         }
         // eprintln!("ScoreCalculator: {} {}", bomb_obstacle[5], chian_obstacle[5]);
         Self {
+            bomb_score,
+            chain_score,
             bomb_obstacle,
             chian_obstacle,
             skill_guage,
@@ -51,7 +59,8 @@ impl ScoreCalculator {
         SCORE_CALCULATOR.with(|s| {
             let obstacle = s.chian_obstacle[chains as usize];
             let skill_guage = Self::decrease_skill_guage(chains);
-            action::ActionResult::new(chains, obstacle, skill_guage, fire_height)
+            let score = s.chain_score[chains as usize];
+            action::ActionResult::new(chains, obstacle, skill_guage, fire_height, score)
         })
     }
 
@@ -59,7 +68,8 @@ impl ScoreCalculator {
         SCORE_CALCULATOR.with(|s| {
             let obstacle = s.bomb_obstacle[bomb as usize] + s.chian_obstacle[chains as usize];
             let skill_guage = Self::decrease_skill_guage(chains);
-            action::ActionResult::new(chains, obstacle, skill_guage, fire_height)
+            let score = s.bomb_score[bomb as usize] + s.chain_score[chains as usize];
+            action::ActionResult::new(chains, obstacle, skill_guage, fire_height, score)
         })
     }
 
@@ -78,5 +88,7 @@ fn score_calculator_test() {
     SCORE_CALCULATOR.with(|s| {
         assert_eq!(s.bomb_obstacle[5], 16);
         assert_eq!(s.chian_obstacle[5], 4);
+        assert_eq!(s.bomb_score[5], 33);
+        assert_eq!(s.chain_score[5], 9);
     });
 }
@@ -0,0 +1,87 @@
+use super::action;
+use super::player;
+use super::replay;
+use super::types::RawPack;
+
+// 戦略に渡す1ターン分の局面
+pub struct Snapshot<'a> {
+    pub turn: usize,
+    pub rest_time_in_milli: usize,
+    pub player: &'a player::Player,
+    pub enemy: &'a player::Player,
+    pub packs: &'a [RawPack],
+}
+
+pub enum Decision {
+    Action(action::Action),
+    // 以降のターンは他の戦略が決めない限りこの計画を再生する
+    Plan(replay::Replay),
+}
+
+pub trait Strategy {
+    // 決められなければNoneを返して次の戦略に任せる
+    fn think(&mut self, s: &Snapshot) -> Option<Decision>;
+
+    // 対戦開始時に出力するAIの名前
+    fn name(&self) -> &str {
+        "test-best-ai"
+    }
+}
+
+impl<S: Strategy + ?Sized> Strategy for Box<S> {
+    fn think(&mut self, s: &Snapshot) -> Option<Decision> {
+        (**self).think(s)
+    }
+
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+}
+
+// 優先度の高い順に試して最初に決まったものを使う
+#[derive(Default)]
+pub struct Priority {
+    strategies: Vec<Box<dyn Strategy>>,
+}
+
+impl Priority {
+    pub fn new() -> Self {
+        Self { strategies: Vec::new() }
+    }
+
+    pub fn then<S: Strategy + 'static>(mut self, strategy: S) -> Self {
+        self.strategies.push(Box::new(strategy));
+        self
+    }
+}
+
+impl Strategy for Priority {
+    fn think(&mut self, s: &Snapshot) -> Option<Decision> {
+        self.strategies.iter_mut().find_map(|strategy| strategy.think(s))
+    }
+}
+
+#[cfg(test)]
+struct Fixed(Option<action::Action>);
+
+#[cfg(test)]
+impl Strategy for Fixed {
+    fn think(&mut self, _: &Snapshot) -> Option<Decision> {
+        self.0.clone().map(Decision::Action)
+    }
+}
+
+#[test]
+fn priority_test() {
+    let player = player::Player::default();
+    let s = Snapshot { turn: 0, rest_time_in_milli: 0, player: &player, enemy: &player, packs: &[] };
+    let skill = action::Action::UseSkill;
+    let put = action::Action::PutBlock { pos: 3, rot: 1 };
+
+    let mut strategy = Priority::new().then(Fixed(None)).then(Fixed(Some(skill.clone()))).then(Fixed(Some(put)));
+    match strategy.think(&s) {
+        Some(Decision::Action(a)) => assert_eq!(a, skill),
+        _ => unreachable!(),
+    }
+    assert!(Priority::new().then(Fixed(None)).think(&s).is_none());
+}
@@ -0,0 +1,147 @@
+use std::cmp::Reverse;
+use std::sync::Mutex;
+
+// ロックの競合を減らすため分割する
+const SHARDS: usize = 64;
+// 1つのハッシュ値が入れる場所の数
+const WAYS: usize = 2;
+
+pub const DEFAULT_CAPACITY: usize = 1 << 20;
+
+// key=0は空き
+#[derive(Clone, Copy, Default)]
+struct Entry {
+    key: u64,
+    eval: i64,
+    depth: u32,
+}
+
+impl Entry {
+    // 浅いほど、同じ深さなら評価値が高いほど価値がある
+    fn value(&self) -> (Reverse<u32>, i64) {
+        (Reverse(self.depth), self.eval)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TableStats {
+    pub probes: usize,
+    // 既にもっと良い形で来ていたので捨てた数
+    pub hits: usize,
+    // 同じ状態が前より浅いか良い評価値で来て書き換えた数
+    pub updates: usize,
+    pub stores: usize,
+    // 別の状態を追い出して書き込んだ数
+    pub replaces: usize,
+}
+
+impl TableStats {
+    pub fn hit_rate(&self) -> f64 {
+        if self.probes == 0 { 0.0 } else { self.hits as f64 / self.probes as f64 }
+    }
+
+    fn add(&mut self, other: &TableStats) {
+        self.probes += other.probes;
+        self.hits += other.hits;
+        self.updates += other.updates;
+        self.stores += other.stores;
+        self.replaces += other.replaces;
+    }
+}
+
+struct Shard {
+    entries: Vec<Entry>,
+    stats: TableStats,
+}
+
+// 状態のハッシュごとに一番良い深さと評価値を覚える固定サイズの表
+pub struct TranspositionTable {
+    shards: Vec<Mutex<Shard>>,
+    buckets_per_shard: usize,
+}
+
+impl TranspositionTable {
+    // capacityは全体の要素数 (分割しやすいよう2の冪に切り上げる)
+    pub fn new(capacity: usize) -> Self {
+        let buckets_per_shard = (capacity / SHARDS / WAYS).max(1).next_power_of_two();
+        let shards = (0..SHARDS).map(|_| Mutex::new(Shard {
+            entries: vec![Entry::default(); buckets_per_shard * WAYS],
+            stats: Default::default(),
+        })).collect();
+        Self { shards, buckets_per_shard }
+    }
+
+    pub fn capacity(&self) -> usize {
+        SHARDS * self.buckets_per_shard * WAYS
+    }
+
+    // 初めての状態か、前より浅いか同じ深さで評価値が高ければ記録してtrueを返す
+    // 入りきらないときはバケツの中で一番価値の低いものを追い出す
+    pub fn insert(&self, key: u64, depth: usize, eval: i64) -> bool {
+        let key = if key == 0 { 1 } else { key };
+        let new = Entry { key, eval, depth: depth as u32 };
+        let mut shard = self.shards[(key >> 58) as usize % SHARDS].lock().unwrap();
+        let bucket = (key as usize) & (self.buckets_per_shard - 1);
+        let Shard { entries, stats } = &mut *shard;
+        let bucket = &mut entries[bucket * WAYS..(bucket + 1) * WAYS];
+        stats.probes += 1;
+
+        if let Some(e) = bucket.iter_mut().find(|e| e.key == key) {
+            if new.value() > e.value() {
+                *e = new;
+                stats.updates += 1;
+                return true;
+            }
+            stats.hits += 1;
+            return false;
+        }
+
+        let victim = bucket.iter_mut().min_by_key(|e| (e.key != 0, e.value())).unwrap();
+        if victim.key != 0 {
+            stats.replaces += 1;
+        }
+        *victim = new;
+        stats.stores += 1;
+        true
+    }
+
+    pub fn stats(&self) -> TableStats {
+        let mut stats = TableStats::default();
+        self.shards.iter().for_each(|s| stats.add(&s.lock().unwrap().stats));
+        stats
+    }
+}
+
+#[test]
+fn transposition_test_insert() {
+    let table = TranspositionTable::new(1000);
+    assert_eq!(table.capacity(), 1024);
+
+    assert!(table.insert(10, 3, 100));
+    assert!(!table.insert(10, 3, 100));
+    assert!(!table.insert(10, 4, 1000));
+    // 同じ深さで良い評価値か、浅ければ通す
+    assert!(table.insert(10, 3, 101));
+    assert!(table.insert(10, 2, 0));
+    assert!(!table.insert(10, 3, 1000));
+    assert!(table.insert(0, 1, 0));
+
+    let stats = table.stats();
+    assert_eq!(stats, TableStats { probes: 7, hits: 3, updates: 2, stores: 2, replaces: 0 });
+    assert!((stats.hit_rate() - 3.0 / 7.0).abs() < 1e-9);
+}
+
+#[test]
+fn transposition_test_replace() {
+    // 1バケツ (2要素) ずつしかない表
+    let table = TranspositionTable::new(1);
+    let key = |i: u64| i << 8 | 5;
+    assert!(table.insert(key(1), 1, 0));
+    assert!(table.insert(key(2), 5, 0));
+    // 深い方が追い出される
+    assert!(table.insert(key(3), 2, 0));
+    assert!(!table.insert(key(1), 1, 0));
+    assert!(!table.insert(key(3), 2, 0));
+    assert!(table.insert(key(2), 5, 0));
+    assert_eq!(table.stats().replaces, 2);
+}